@@ -0,0 +1,67 @@
+use std::{env, path::PathBuf};
+
+/// Where the library lives before `--db`/`BZ_PLAYER_DB` existed.
+const LEGACY_DB_PATH: &str = "song.db";
+
+pub struct Config {
+    pub db_path: PathBuf,
+    /// An old `./song.db` that can be moved into `db_path` with `db import`.
+    pub legacy_db: Option<PathBuf>,
+}
+
+impl Config {
+    pub const DB_ENV: &'static str = "BZ_PLAYER_DB";
+
+    pub fn from_args() -> Result<Self, String> {
+        let mut args = env::args().skip(1);
+        let mut db_flag = None;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--db" => match args.next() {
+                    Some(path) => db_flag = Some(PathBuf::from(path)),
+                    None => return Err("--db needs a path".to_string()),
+                },
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+
+        let db_override = db_flag.or_else(|| {
+            env::var_os(Self::DB_ENV)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+        });
+
+        let config = match db_override {
+            Some(db_path) => Self {
+                db_path,
+                legacy_db: None,
+            },
+            None => {
+                let db_path = Self::default_db_path();
+                let legacy_db = PathBuf::from(LEGACY_DB_PATH);
+                let legacy_db = (legacy_db.is_file() && legacy_db != db_path).then_some(legacy_db);
+                Self { db_path, legacy_db }
+            }
+        };
+
+        Ok(config)
+    }
+
+    /// `$XDG_DATA_HOME/bz_player/library.db`, or the old relative path when
+    /// the platform has no data directory.
+    pub fn default_db_path() -> PathBuf {
+        match dirs::data_dir() {
+            Some(data_dir) => data_dir.join("bz_player").join("library.db"),
+            None => PathBuf::from(LEGACY_DB_PATH),
+        }
+    }
+
+    pub fn usage() -> String {
+        format!(
+            "usage: bz_player [--db <path>]\n\nThe library defaults to {}, {} overrides it.",
+            Self::default_db_path().display(),
+            Self::DB_ENV
+        )
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt::{Display, Formatter, Result};
 
+use crate::query::QueryError;
+
 #[derive(Debug)]
 pub enum SongError {
     InvalidSongPath,
@@ -11,7 +13,7 @@ impl Display for SongError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
             Self::InvalidSongPath => write!(f, "Song Path Cannot be Found"),
-            Self::InvalidSongFormat => write!(f, "Only ogg, wav, mp3 are supported"),
+            Self::InvalidSongFormat => write!(f, "Only ogg, wav, mp3, flac, m4a are supported"),
             Self::SongAccessError => write!(f, "No Access to Song File"),
         }
     }
@@ -23,6 +25,7 @@ pub enum PlayerError {
     LastSong,
     EmptyQueue,
     IndexOutOfBounds,
+    AudioUnavailable(String),
 }
 
 impl Display for PlayerError {
@@ -32,6 +35,7 @@ impl Display for PlayerError {
             Self::LastSong => write!(f, "No More Song in the Queue"),
             Self::EmptyQueue => write!(f, "Queue is Empty"),
             Self::IndexOutOfBounds => write!(f, "Given Song Index is Invalid"),
+            Self::AudioUnavailable(err) => write!(f, "No Audio Output: {}", err),
         }
     }
 }
@@ -43,7 +47,18 @@ pub enum SongBaseError {
     SongError(SongError),
     InvalidPath,
     DatabaseError(String),
-    NameAlreadyExist
+    NameAlreadyExist,
+    SchemaTooNew(u32, u32),
+    MigrationFailed(u32, String),
+    BackupFailed(String),
+    FolderFailed(String),
+    InvalidQuery(QueryError),
+    WatchFailed(String),
+    InvalidPattern(String),
+    ScanCancelled,
+    RatingNotWritten(String),
+    InvalidLabel,
+    SmartPlaylist,
 }
 
 impl Display for SongBaseError {
@@ -55,10 +70,39 @@ impl Display for SongBaseError {
             Self::InvalidPath => write!(f, "The given path does not exists"),
             Self::DatabaseError(err) => write!(f, "Database error: {}", err),
             Self::NameAlreadyExist => write!(f, "Given Name Already Exist"),
+            Self::SchemaTooNew(found, supported) => write!(
+                f,
+                "Library is from a Newer Version (schema {}, this build knows {})",
+                found, supported
+            ),
+            Self::MigrationFailed(version, err) => {
+                write!(
+                    f,
+                    "Upgrading the Library to schema {} failed: {}",
+                    version, err
+                )
+            }
+            Self::BackupFailed(err) => write!(f, "Can't Back Up the Library: {}", err),
+            Self::FolderFailed(err) => write!(f, "Can't Create the Library Folder: {}", err),
+            Self::InvalidQuery(err) => write!(f, "Invalid Search: {}", err),
+            Self::WatchFailed(err) => write!(f, "Can't Watch the Library: {}", err),
+            Self::InvalidPattern(err) => write!(f, "Invalid Pattern: {}", err),
+            Self::ScanCancelled => write!(f, "Scan Cancelled, Nothing Marked Missing"),
+            Self::InvalidLabel => write!(f, "A Label is One Word, Like workout or focus"),
+            Self::SmartPlaylist => write!(f, "Smart Playlists are Filled by Their Rule"),
+            Self::RatingNotWritten(err) => {
+                write!(f, "Rating Saved, but Not Written to the File: {}", err)
+            }
         }
     }
 }
 
+impl From<QueryError> for SongBaseError {
+    fn from(value: QueryError) -> Self {
+        Self::InvalidQuery(value)
+    }
+}
+
 impl From<rusqlite::Error> for SongBaseError {
     fn from(value: rusqlite::Error) -> Self {
         Self::DatabaseError(value.to_string())
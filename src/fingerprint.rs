@@ -0,0 +1,255 @@
+use std::{
+    f32::consts::PI,
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
+
+use symphonia::core::{
+    audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+    formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+/// Audio is brought down to this rate before it's fingerprinted, plenty for
+/// the bands that are looked at.
+const SAMPLE_RATE: u32 = 11025;
+/// Only the start of a song is fingerprinted, like Chromaprint does.
+const MAX_SECONDS: u32 = 60;
+const FRAME_SIZE: usize = 2048;
+/// About 23ms, small enough that copies starting a little apart still line
+/// up with some frame.
+const FRAME_STEP: usize = 256;
+/// Everything above is filtered out before the rate is brought down, so it
+/// can't fold back into the bands.
+const CUTOFF_HZ: f32 = 2500.0;
+const FILTER_POLES: usize = 4;
+/// 33 bands between these give 32 bits per frame.
+const BANDS: usize = 33;
+const LOWEST_HZ: f32 = 300.0;
+const HIGHEST_HZ: f32 = 2000.0;
+/// Frames two fingerprints may be shifted by, for encoder delay and padding.
+const MAX_OFFSET: isize = 16;
+/// Overlapping frames needed before a comparison means anything.
+const MIN_OVERLAP: usize = 50;
+
+/// Hex BLAKE3 of the file's bytes.
+pub fn content_hash(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = blake3::Hasher::new();
+    let mut chunk = vec![0; 64 * 1024];
+    loop {
+        match file.read(&mut chunk)? {
+            0 => break,
+            read => hasher.update(&chunk[..read]),
+        };
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// What a song sounds like, one 32 bit word per frame: each bit tells
+/// whether the energy difference between two neighbouring bands grew since
+/// the last frame. Decoding the same recording from another format or
+/// bitrate flips only a few bits.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Fingerprint(Vec<u32>);
+
+impl Fingerprint {
+    /// Decodes the start of the file, `None` when it can't be.
+    pub fn compute(path: &Path) -> Option<Self> {
+        let samples = decode_mono(path)?;
+        if samples.len() < FRAME_SIZE {
+            return None;
+        }
+
+        let window: Vec<f32> = (0..FRAME_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FRAME_SIZE as f32).cos())
+            .collect();
+        let band_edges: Vec<usize> = (0..=BANDS)
+            .map(|band| {
+                let hz = LOWEST_HZ * (HIGHEST_HZ / LOWEST_HZ).powf(band as f32 / BANDS as f32);
+                (hz * FRAME_SIZE as f32 / SAMPLE_RATE as f32).round() as usize
+            })
+            .collect();
+
+        let mut words = Vec::new();
+        let mut previous: Option<Vec<f32>> = None;
+        for start in (0..=samples.len() - FRAME_SIZE).step_by(FRAME_STEP) {
+            let mut re: Vec<f32> = samples[start..start + FRAME_SIZE]
+                .iter()
+                .zip(&window)
+                .map(|(sample, weight)| sample * weight)
+                .collect();
+            let mut im = vec![0.0; FRAME_SIZE];
+            fft(&mut re, &mut im);
+
+            let energies: Vec<f32> = band_edges
+                .windows(2)
+                .map(|edge| {
+                    (edge[0]..edge[1].max(edge[0] + 1))
+                        .map(|bin| re[bin] * re[bin] + im[bin] * im[bin])
+                        .sum()
+                })
+                .collect();
+
+            if let Some(previous) = &previous {
+                let mut word = 0u32;
+                for band in 0..BANDS - 1 {
+                    let now = energies[band] - energies[band + 1];
+                    let before = previous[band] - previous[band + 1];
+                    if now - before > 0.0 {
+                        word |= 1 << band;
+                    }
+                }
+                words.push(word);
+            }
+            previous = Some(energies);
+        }
+        Some(Self(words))
+    }
+
+    /// How alike two fingerprints are, from 0 (unrelated) to 1 (the same),
+    /// at the best alignment. Too little overlap counts as unrelated.
+    pub fn similarity(&self, other: &Self) -> f32 {
+        let mut best = 0.0f32;
+        for offset in -MAX_OFFSET..=MAX_OFFSET {
+            let (a, b) = match offset >= 0 {
+                true => (self.0.get(offset as usize..), Some(&other.0[..])),
+                false => (Some(&self.0[..]), other.0.get(offset.unsigned_abs()..)),
+            };
+            let (Some(a), Some(b)) = (a, b) else {
+                continue;
+            };
+
+            let overlap = a.len().min(b.len());
+            if overlap < MIN_OVERLAP {
+                continue;
+            }
+            let differing: u32 = a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum();
+            best = best.max(1.0 - differing as f32 / (overlap as f32 * 32.0));
+        }
+        best
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.0.iter().flat_map(|word| word.to_le_bytes()).collect()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        Self(
+            bytes
+                .chunks_exact(4)
+                .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                .collect(),
+        )
+    }
+}
+
+/// The first `MAX_SECONDS` of the default track, mixed down to mono,
+/// low-passed and averaged down to `SAMPLE_RATE`.
+fn decode_mono(path: &Path) -> Option<Vec<f32>> {
+    let file = File::open(path).ok()?;
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let source = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut format = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()?
+        .format;
+    let track = format.default_track()?;
+    let track_id = track.id;
+    let source_rate = track.codec_params.sample_rate?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .ok()?;
+
+    let step = source_rate as f32 / SAMPLE_RATE as f32;
+    let smoothing = 1.0 - (-2.0 * PI * CUTOFF_HZ / source_rate as f32).exp();
+    let mut filter = [0.0f32; FILTER_POLES];
+    let max_samples = (SAMPLE_RATE * MAX_SECONDS) as usize;
+    let mut samples = Vec::with_capacity(max_samples);
+    let (mut sum, mut count, mut position) = (0.0f32, 0usize, 0.0f32);
+
+    while samples.len() < max_samples {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(_) => break,
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A damaged packet is skipped, the rest may still decode
+            Err(SymphoniaError::DecodeError(_)) => continue,
+            Err(_) => break,
+        };
+
+        let channels = decoded.spec().channels.count().max(1);
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, *decoded.spec());
+        buffer.copy_interleaved_ref(decoded);
+        for frame in buffer.samples().chunks(channels) {
+            let mut sample = frame.iter().sum::<f32>() / channels as f32;
+            for pole in filter.iter_mut() {
+                *pole += smoothing * (sample - *pole);
+                sample = *pole;
+            }
+            sum += sample;
+            count += 1;
+            position += 1.0;
+            if position >= step {
+                samples.push(sum / count as f32);
+                (sum, count) = (0.0, 0);
+                position -= step;
+            }
+        }
+    }
+    Some(samples)
+}
+
+/// In-place radix-2 FFT, `re.len()` has to be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::{Path, PathBuf};
+
+use crate::song::Playlist;
+
+/// The file entries of an M3U or M3U8 playlist, in order. Comments and
+/// `#EXT` directives are skipped, the length and name in `#EXTINF` are
+/// taken from the files themselves instead.
+pub fn entries(text: &str) -> Vec<String> {
+    text.trim_start_matches('\u{feff}')
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect()
+}
+
+/// The playlist's text. M3U8 is UTF-8, a plain `.m3u` is usually written in
+/// the system's codepage instead, so one that isn't valid UTF-8 is read as
+/// Latin-1.
+pub fn decode(bytes: &[u8], path: &Path) -> String {
+    let is_m3u8 = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m3u8"));
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(_) if !is_m3u8 => bytes.iter().map(|&byte| byte as char).collect(),
+        Err(_) => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Where an entry points to. Relative entries are relative to the folder
+/// the playlist is in, `file://` URLs are decoded.
+pub fn resolve(entry: &str, playlist_dir: &Path) -> PathBuf {
+    let path = match entry.strip_prefix("file://") {
+        Some(url) => PathBuf::from(percent_decode(url)),
+        None => PathBuf::from(entry),
+    };
+    match path.is_absolute() {
+        true => path,
+        false => playlist_dir.join(path),
+    }
+}
+
+/// An extended M3U with each song's length in seconds (-1 when unknown)
+/// and `Artist - Title` name, pointing to the files by absolute path.
+pub fn write(playlist: &Playlist) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    text.push_str(&format!("#PLAYLIST:{}\n", playlist.playlist_name));
+    for song in &playlist.songs {
+        let seconds = song
+            .tags
+            .duration_ms
+            .map_or(-1, |duration| (duration / 1000) as i64);
+        text.push_str(&format!("#EXTINF:{},{}\n", seconds, song.display_name()));
+        text.push_str(&format!("{}\n", song.song_path.display()));
+    }
+    text
+}
+
+/// `%20` -> ` `, anything that isn't a valid escape is kept as it is.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = (bytes[index] == b'%')
+            .then(|| text.get(index + 1..index + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_skip_comments_and_blank_lines() {
+        let text = "\u{feff}#EXTM3U\n#EXTINF:215,Queen - Bohemian Rhapsody\n\
+            Queen/bohemian.mp3\r\n\n  # a note\n /music/other.ogg \n";
+        assert_eq!(entries(text), ["Queen/bohemian.mp3", "/music/other.ogg"]);
+    }
+
+    #[test]
+    fn resolve_relative_absolute_and_file_urls() {
+        let playlist_dir = Path::new("/lists");
+        assert_eq!(
+            resolve("Queen/song.mp3", playlist_dir),
+            PathBuf::from("/lists/Queen/song.mp3")
+        );
+        assert_eq!(
+            resolve("/music/song.mp3", playlist_dir),
+            PathBuf::from("/music/song.mp3")
+        );
+        assert_eq!(
+            resolve("file:///music/My%20Song.mp3", playlist_dir),
+            PathBuf::from("/music/My Song.mp3")
+        );
+    }
+
+    #[test]
+    fn percent_decode_keeps_broken_escapes() {
+        assert_eq!(percent_decode("a%20b%2Fc"), "a b/c");
+        assert_eq!(percent_decode("caf%C3%A9"), "café");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%2"), "%zz%2");
+    }
+
+    #[test]
+    fn decode_reads_latin1_m3u_but_not_m3u8() {
+        let latin1 = b"caf\xe9.mp3\n";
+        assert_eq!(decode(latin1, Path::new("list.m3u")), "café.mp3\n");
+        assert_eq!(decode(latin1, Path::new("list.m3u8")), "caf\u{fffd}.mp3\n");
+        assert_eq!(
+            decode("café.mp3".as_bytes(), Path::new("list.m3u")),
+            "café.mp3"
+        );
+    }
+}
@@ -1,15 +1,29 @@
-use std::io;
+use std::{io, process};
 
+mod config;
 mod error;
+mod fingerprint;
+mod m3u;
+mod migrations;
 mod player;
+mod query;
 mod song;
 mod song_base;
+mod tags;
 mod ui;
 mod utility;
 
 fn main() -> io::Result<()> {
+    let config = match config::Config::from_args() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("bz_player: {}\n\n{}", err, config::Config::usage());
+            process::exit(2);
+        }
+    };
+
     let mut terminal = ui::init()?;
-    let app_result = ui::App::new().run(&mut terminal);
+    let app_result = ui::App::new(config).run(&mut terminal);
     ui::restore()?;
     app_result
 }
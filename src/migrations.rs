@@ -0,0 +1,679 @@
+use std::{fs, path::Path};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::error::SongBaseError;
+
+/// One step of the schema. `version` is stored in SQLite's `user_version`
+/// once the step is applied, so every step runs exactly once per database.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    sql: &'static str,
+    /// Run after `sql` in the same transaction, for changes SQL alone can't
+    /// make.
+    code: Option<fn(&Connection) -> rusqlite::Result<()>>,
+}
+
+/// Ordered oldest first; only ever append to this list.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "baseline schema",
+        sql: "
+            CREATE TABLE IF NOT EXISTS songs(
+                song_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                song_name TEXT UNIQUE NOT NULL,
+                song_path TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS playlists(
+                playlist_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                playlist_name TEXT UNQIUE NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS playlist_song_link(
+                playlist_id INTEGER,
+                song_id INTEGER,
+                PRIMARY KEY (playlist_id, song_id),
+                FOREIGN KEY (playlist_id) REFERENCES playlists(playlist_id) ON DELETE CASCADE,
+                FOREIGN KEY (song_id) REFERENCES songs(song_id) ON DELETE CASCADE
+            );
+        ",
+        code: None,
+    },
+    Migration {
+        version: 2,
+        description: "unique playlist names",
+        sql: "
+            UPDATE playlists SET playlist_name = playlist_name || ' (' || playlist_id || ')'
+            WHERE playlist_id NOT IN (
+                SELECT MIN(playlist_id) FROM playlists GROUP BY playlist_name
+            );
+
+            CREATE TABLE playlists_new(
+                playlist_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                playlist_name TEXT UNIQUE NOT NULL
+            );
+            INSERT INTO playlists_new (playlist_id, playlist_name)
+                SELECT playlist_id, playlist_name FROM playlists;
+            DROP TABLE playlists;
+            ALTER TABLE playlists_new RENAME TO playlists;
+        ",
+        code: None,
+    },
+    Migration {
+        version: 3,
+        description: "embedded tags",
+        sql: "
+            ALTER TABLE songs ADD COLUMN title TEXT;
+            ALTER TABLE songs ADD COLUMN artist TEXT;
+            ALTER TABLE songs ADD COLUMN album TEXT;
+            ALTER TABLE songs ADD COLUMN album_artist TEXT;
+            ALTER TABLE songs ADD COLUMN track_number INTEGER;
+            ALTER TABLE songs ADD COLUMN disc_number INTEGER;
+            ALTER TABLE songs ADD COLUMN year INTEGER;
+            ALTER TABLE songs ADD COLUMN genre TEXT;
+            ALTER TABLE songs ADD COLUMN duration_ms INTEGER;
+        ",
+        code: None,
+    },
+    Migration {
+        version: 4,
+        description: "albums",
+        sql: "
+            CREATE TABLE albums(
+                album_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                album_name TEXT NOT NULL,
+                album_artist TEXT NOT NULL DEFAULT '',
+                UNIQUE (album_name, album_artist)
+            );
+
+            ALTER TABLE songs ADD COLUMN album_id INTEGER
+                REFERENCES albums(album_id) ON DELETE SET NULL;
+
+            INSERT OR IGNORE INTO albums (album_name, album_artist)
+                SELECT album, COALESCE(album_artist, artist, '') FROM songs
+                WHERE album IS NOT NULL;
+            UPDATE songs SET album_id = (
+                SELECT album_id FROM albums
+                WHERE album_name = songs.album
+                    AND album_artist = COALESCE(songs.album_artist, songs.artist, '')
+            );
+
+            CREATE TRIGGER songs_album_insert AFTER INSERT ON songs
+            WHEN NEW.album IS NOT NULL
+            BEGIN
+                INSERT OR IGNORE INTO albums (album_name, album_artist)
+                    VALUES (NEW.album, COALESCE(NEW.album_artist, NEW.artist, ''));
+                UPDATE songs SET album_id = (
+                    SELECT album_id FROM albums
+                    WHERE album_name = NEW.album
+                        AND album_artist = COALESCE(NEW.album_artist, NEW.artist, '')
+                ) WHERE song_id = NEW.song_id;
+            END;
+
+            CREATE TRIGGER songs_album_update AFTER UPDATE OF album, album_artist, artist ON songs
+            BEGIN
+                INSERT OR IGNORE INTO albums (album_name, album_artist)
+                    SELECT NEW.album, COALESCE(NEW.album_artist, NEW.artist, '')
+                    WHERE NEW.album IS NOT NULL;
+                UPDATE songs SET album_id = (
+                    SELECT album_id FROM albums
+                    WHERE album_name = NEW.album
+                        AND album_artist = COALESCE(NEW.album_artist, NEW.artist, '')
+                ) WHERE song_id = NEW.song_id;
+            END;
+        ",
+        code: None,
+    },
+    Migration {
+        version: 5,
+        description: "full-text search",
+        sql: "
+            CREATE VIRTUAL TABLE songs_fts USING fts5(
+                title, artist, album, song_path,
+                content = 'songs', content_rowid = 'song_id',
+                tokenize = 'unicode61 remove_diacritics 2'
+            );
+            INSERT INTO songs_fts (songs_fts) VALUES ('rebuild');
+
+            CREATE TRIGGER songs_fts_insert AFTER INSERT ON songs BEGIN
+                INSERT INTO songs_fts (rowid, title, artist, album, song_path)
+                    VALUES (NEW.song_id, NEW.title, NEW.artist, NEW.album, NEW.song_path);
+            END;
+
+            CREATE TRIGGER songs_fts_delete AFTER DELETE ON songs BEGIN
+                INSERT INTO songs_fts (songs_fts, rowid, title, artist, album, song_path)
+                    VALUES ('delete', OLD.song_id, OLD.title, OLD.artist, OLD.album, OLD.song_path);
+            END;
+
+            CREATE TRIGGER songs_fts_update AFTER UPDATE OF title, artist, album, song_path
+            ON songs BEGIN
+                INSERT INTO songs_fts (songs_fts, rowid, title, artist, album, song_path)
+                    VALUES ('delete', OLD.song_id, OLD.title, OLD.artist, OLD.album, OLD.song_path);
+                INSERT INTO songs_fts (rowid, title, artist, album, song_path)
+                    VALUES (NEW.song_id, NEW.title, NEW.artist, NEW.album, NEW.song_path);
+            END;
+        ",
+        code: None,
+    },
+    Migration {
+        version: 6,
+        description: "saved search filters",
+        sql: "
+            CREATE TABLE saved_filters(
+                filter_name TEXT PRIMARY KEY,
+                filter_query TEXT NOT NULL
+            );
+        ",
+        code: None,
+    },
+    // Links are repointed in place, so they keep the order they were added in
+    Migration {
+        version: 7,
+        description: "songs keyed by path",
+        sql: "
+            UPDATE OR IGNORE playlist_song_link SET song_id = (
+                SELECT MIN(kept.song_id) FROM songs AS kept JOIN songs
+                    ON songs.song_path = kept.song_path
+                WHERE songs.song_id = playlist_song_link.song_id
+            );
+            DELETE FROM songs WHERE song_id NOT IN (
+                SELECT MIN(song_id) FROM songs GROUP BY song_path
+            );
+            DELETE FROM playlist_song_link WHERE song_id NOT IN (SELECT song_id FROM songs);
+
+            CREATE TABLE songs_new(
+                song_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                song_name TEXT NOT NULL,
+                song_path TEXT UNIQUE NOT NULL,
+                title TEXT,
+                artist TEXT,
+                album TEXT,
+                album_artist TEXT,
+                track_number INTEGER,
+                disc_number INTEGER,
+                year INTEGER,
+                genre TEXT,
+                duration_ms INTEGER,
+                album_id INTEGER REFERENCES albums(album_id) ON DELETE SET NULL
+            );
+            INSERT INTO songs_new SELECT song_id, song_name, song_path, title, artist, album,
+                album_artist, track_number, disc_number, year, genre, duration_ms, album_id
+                FROM songs;
+            DROP TABLE songs;
+            ALTER TABLE songs_new RENAME TO songs;
+            CREATE INDEX songs_song_name ON songs(song_name);
+
+            CREATE TRIGGER songs_album_insert AFTER INSERT ON songs
+            WHEN NEW.album IS NOT NULL
+            BEGIN
+                INSERT OR IGNORE INTO albums (album_name, album_artist)
+                    VALUES (NEW.album, COALESCE(NEW.album_artist, NEW.artist, ''));
+                UPDATE songs SET album_id = (
+                    SELECT album_id FROM albums
+                    WHERE album_name = NEW.album
+                        AND album_artist = COALESCE(NEW.album_artist, NEW.artist, '')
+                ) WHERE song_id = NEW.song_id;
+            END;
+
+            CREATE TRIGGER songs_album_update AFTER UPDATE OF album, album_artist, artist ON songs
+            BEGIN
+                INSERT OR IGNORE INTO albums (album_name, album_artist)
+                    SELECT NEW.album, COALESCE(NEW.album_artist, NEW.artist, '')
+                    WHERE NEW.album IS NOT NULL;
+                UPDATE songs SET album_id = (
+                    SELECT album_id FROM albums
+                    WHERE album_name = NEW.album
+                        AND album_artist = COALESCE(NEW.album_artist, NEW.artist, '')
+                ) WHERE song_id = NEW.song_id;
+            END;
+
+            CREATE TRIGGER songs_fts_insert AFTER INSERT ON songs BEGIN
+                INSERT INTO songs_fts (rowid, title, artist, album, song_path)
+                    VALUES (NEW.song_id, NEW.title, NEW.artist, NEW.album, NEW.song_path);
+            END;
+
+            CREATE TRIGGER songs_fts_delete AFTER DELETE ON songs BEGIN
+                INSERT INTO songs_fts (songs_fts, rowid, title, artist, album, song_path)
+                    VALUES ('delete', OLD.song_id, OLD.title, OLD.artist, OLD.album, OLD.song_path);
+            END;
+
+            CREATE TRIGGER songs_fts_update AFTER UPDATE OF title, artist, album, song_path
+            ON songs BEGIN
+                INSERT INTO songs_fts (songs_fts, rowid, title, artist, album, song_path)
+                    VALUES ('delete', OLD.song_id, OLD.title, OLD.artist, OLD.album, OLD.song_path);
+                INSERT INTO songs_fts (rowid, title, artist, album, song_path)
+                    VALUES (NEW.song_id, NEW.title, NEW.artist, NEW.album, NEW.song_path);
+            END;
+
+            INSERT INTO songs_fts (songs_fts) VALUES ('rebuild');
+        ",
+        code: None,
+    },
+    Migration {
+        version: 8,
+        description: "file stamps for incremental scans",
+        sql: "
+            ALTER TABLE songs ADD COLUMN file_mtime INTEGER;
+            ALTER TABLE songs ADD COLUMN file_size INTEGER;
+            ALTER TABLE songs ADD COLUMN missing INTEGER NOT NULL DEFAULT 0;
+        ",
+        code: None,
+    },
+    Migration {
+        version: 9,
+        description: "library roots",
+        sql: "
+            CREATE TABLE library_roots(
+                root_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                root_path TEXT UNIQUE NOT NULL
+            );
+        ",
+        code: None,
+    },
+    Migration {
+        version: 10,
+        description: "scan exclusions",
+        sql: "
+            CREATE TABLE scan_exclusions(
+                pattern TEXT PRIMARY KEY
+            );
+            INSERT INTO scan_exclusions (pattern) VALUES ('node_modules'), ('target');
+
+            CREATE TABLE settings(
+                setting_key TEXT PRIMARY KEY,
+                setting_value TEXT NOT NULL
+            );
+            INSERT INTO settings (setting_key, setting_value) VALUES ('scan_max_depth', '32');
+        ",
+        code: None,
+    },
+    Migration {
+        version: 11,
+        description: "duplicate detection",
+        sql: "
+            ALTER TABLE songs ADD COLUMN content_hash TEXT;
+            ALTER TABLE songs ADD COLUMN fingerprint BLOB;
+            ALTER TABLE songs ADD COLUMN merged_into INTEGER
+                REFERENCES songs(song_id) ON DELETE SET NULL;
+            CREATE INDEX songs_content_hash ON songs(content_hash);
+        ",
+        code: None,
+    },
+    Migration {
+        version: 12,
+        description: "listening history",
+        sql: "
+            CREATE TABLE plays(
+                play_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                song_id INTEGER NOT NULL,
+                played_at INTEGER NOT NULL,
+                listened_ms INTEGER NOT NULL,
+                FOREIGN KEY (song_id) REFERENCES songs(song_id) ON DELETE CASCADE
+            );
+            CREATE INDEX plays_played_at ON plays(played_at);
+            CREATE INDEX plays_song_id ON plays(song_id);
+
+            ALTER TABLE songs ADD COLUMN play_count INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE songs ADD COLUMN skip_count INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE songs ADD COLUMN last_played INTEGER;
+        ",
+        code: None,
+    },
+    // Every file counts as changed on the next scan, so ratings already in
+    // the tags come in
+    Migration {
+        version: 13,
+        description: "ratings and favorites",
+        sql: "
+            ALTER TABLE songs ADD COLUMN rating INTEGER CHECK (rating BETWEEN 1 AND 5);
+            ALTER TABLE songs ADD COLUMN favorite INTEGER NOT NULL DEFAULT 0;
+            UPDATE songs SET file_mtime = NULL;
+        ",
+        code: None,
+    },
+    Migration {
+        version: 14,
+        description: "song labels",
+        sql: "
+            CREATE TABLE labels(
+                label_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                label_name TEXT UNIQUE NOT NULL
+            );
+            CREATE TABLE song_labels(
+                label_id INTEGER NOT NULL,
+                song_id INTEGER NOT NULL,
+                PRIMARY KEY (label_id, song_id),
+                FOREIGN KEY (label_id) REFERENCES labels(label_id) ON DELETE CASCADE,
+                FOREIGN KEY (song_id) REFERENCES songs(song_id) ON DELETE CASCADE
+            );
+            CREATE INDEX song_labels_song_id ON song_labels(song_id);
+        ",
+        code: None,
+    },
+    Migration {
+        version: 15,
+        description: "smart playlists",
+        sql: "
+            ALTER TABLE playlists ADD COLUMN playlist_type TEXT NOT NULL DEFAULT 'manual'
+                CHECK (playlist_type IN ('manual', 'smart'));
+            ALTER TABLE playlists ADD COLUMN playlist_rule TEXT;
+        ",
+        code: None,
+    },
+    // Existing playlists keep the order their songs were added in
+    Migration {
+        version: 16,
+        description: "playlist order",
+        sql: "
+            ALTER TABLE playlist_song_link ADD COLUMN position INTEGER NOT NULL DEFAULT 0;
+            UPDATE playlist_song_link SET position = 1 + (
+                SELECT COUNT(*) FROM playlist_song_link AS earlier
+                WHERE earlier.playlist_id = playlist_song_link.playlist_id
+                    AND earlier.rowid < playlist_song_link.rowid
+            );
+        ",
+        code: None,
+    },
+    Migration {
+        version: 17,
+        description: "canonical song paths",
+        sql: "",
+        code: Some(canonical_song_paths),
+    },
+    // Ratings so far could have come from the files, they stay overwritable
+    Migration {
+        version: 18,
+        description: "ratings set in the library",
+        sql: "
+            ALTER TABLE songs ADD COLUMN rating_set INTEGER NOT NULL DEFAULT 0;
+        ",
+        code: None,
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+pub fn current_version(conn: &Connection) -> Result<u32, SongBaseError> {
+    Ok(conn.pragma_query_value(None, "user_version", |row| row.get(0))?)
+}
+
+/// Brings the database up to `latest_version`, each step in its own
+/// transaction. A file backed database is copied aside before anything is
+/// changed, and one written by a newer build is refused untouched.
+pub fn migrate(conn: &mut Connection) -> Result<u32, SongBaseError> {
+    let current = current_version(conn)?;
+    let latest = latest_version();
+
+    if current > latest {
+        return Err(SongBaseError::SchemaTooNew(current, latest));
+    }
+    if current == latest {
+        return Ok(current);
+    }
+
+    if let Some(db_path) = conn.path().filter(|path| !path.is_empty()) {
+        if has_tables(conn)? {
+            backup(Path::new(db_path), current)?;
+        }
+    }
+
+    // Table rebuilds would cascade-delete links otherwise
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let result = apply(conn, current);
+    conn.pragma_update(None, "foreign_keys", true)?;
+    result?;
+
+    Ok(latest)
+}
+
+fn apply(conn: &mut Connection, current: u32) -> Result<(), SongBaseError> {
+    for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+        let failed = |err: rusqlite::Error| {
+            SongBaseError::MigrationFailed(
+                migration.version,
+                format!("{}: {}", migration.description, err),
+            )
+        };
+
+        let tx = conn.transaction().map_err(failed)?;
+        tx.execute_batch(migration.sql).map_err(failed)?;
+        if let Some(code) = migration.code {
+            code(&tx).map_err(failed)?;
+        }
+        tx.pragma_update(None, "user_version", migration.version)
+            .map_err(failed)?;
+        tx.commit().map_err(failed)?;
+    }
+
+    Ok(())
+}
+
+/// Earlier builds stored paths the way the scanned folder was typed, often
+/// relative, while scans look songs up by canonical path now. Where a rescan
+/// already added the canonical path, the old row's history moves over to it
+/// and the old row goes. Paths that don't resolve from here are left alone.
+fn canonical_song_paths(conn: &Connection) -> rusqlite::Result<()> {
+    let songs: Vec<(u32, String)> = conn
+        .prepare("SELECT song_id, song_path FROM songs")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<_, _>>()?;
+
+    for (song_id, song_path) in songs {
+        let Ok(canonical) = fs::canonicalize(&song_path) else {
+            continue;
+        };
+        let canonical = canonical.to_string_lossy().to_string();
+        if canonical == song_path {
+            continue;
+        }
+
+        let existing: Option<u32> = conn
+            .query_row(
+                "SELECT song_id FROM songs WHERE song_path = ?1",
+                [&canonical],
+                |row| row.get(0),
+            )
+            .optional()?;
+        match existing {
+            Some(keep) => fold_song(conn, keep, song_id)?,
+            None => {
+                conn.execute(
+                    "UPDATE songs SET song_path = ?2 WHERE song_id = ?1",
+                    params![song_id, canonical],
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Moves everything of `from` to `keep` and deletes it. Written against the
+/// schema as of version 17, later columns don't exist yet when it runs.
+fn fold_song(conn: &Connection, keep: u32, from: u32) -> rusqlite::Result<()> {
+    conn.execute_batch(&format!(
+        "INSERT OR IGNORE INTO playlist_song_link (playlist_id, song_id, position)
+            SELECT playlist_id, {keep}, position FROM playlist_song_link WHERE song_id = {from};
+        DELETE FROM playlist_song_link WHERE song_id = {from};
+        INSERT OR IGNORE INTO song_labels (label_id, song_id)
+            SELECT label_id, {keep} FROM song_labels WHERE song_id = {from};
+        DELETE FROM song_labels WHERE song_id = {from};
+        UPDATE plays SET song_id = {keep} WHERE song_id = {from};
+        UPDATE songs SET
+            play_count = play_count + (SELECT play_count FROM songs WHERE song_id = {from}),
+            skip_count = skip_count + (SELECT skip_count FROM songs WHERE song_id = {from}),
+            last_played = (SELECT MAX(last_played) FROM songs WHERE song_id IN ({keep}, {from})),
+            rating = COALESCE(rating, (SELECT rating FROM songs WHERE song_id = {from})),
+            favorite = favorite OR (SELECT favorite FROM songs WHERE song_id = {from})
+        WHERE song_id = {keep};
+        UPDATE songs SET merged_into = {keep} WHERE merged_into = {from};
+        DELETE FROM songs WHERE song_id = {from};"
+    ))
+}
+
+fn has_tables(conn: &Connection) -> Result<bool, SongBaseError> {
+    let count: u32 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+fn backup(db_path: &Path, version: u32) -> Result<(), SongBaseError> {
+    let mut backup_path = db_path.as_os_str().to_owned();
+    backup_path.push(format!(".v{}.bak", version));
+
+    fs::copy(db_path, &backup_path)
+        .map(|_| ())
+        .map_err(|err| SongBaseError::BackupFailed(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process, sync::mpsc};
+
+    use super::*;
+    use crate::song_base::SongBase;
+
+    fn query_u32(conn: &Connection, sql: &str) -> u32 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    /// A database as a build that only knew up to `version` left it.
+    fn migrate_to(conn: &Connection, version: u32) {
+        for migration in MIGRATIONS.iter().take_while(|m| m.version <= version) {
+            conn.execute_batch(migration.sql).unwrap();
+        }
+        conn.pragma_update(None, "user_version", version).unwrap();
+    }
+
+    #[test]
+    fn canonical_song_paths_fold_into_the_rescanned_row() {
+        let dir = env::temp_dir().join(format!("bz_player_migrations_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("song.mp3"), b"").unwrap();
+        let canonical = fs::canonicalize(dir.join("song.mp3")).unwrap();
+        let typed = dir
+            .join("..")
+            .join(dir.file_name().unwrap())
+            .join("song.mp3");
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&conn, 16);
+        conn.execute(
+            "INSERT INTO songs (song_id, song_name, song_path, play_count) VALUES
+                (1, 'song.mp3', ?1, 2),
+                (2, 'song.mp3', ?2, 0)",
+            params![typed.to_string_lossy(), canonical.to_string_lossy()],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "INSERT INTO playlists (playlist_id, playlist_name) VALUES (1, 'Kept');
+            INSERT INTO playlist_song_link (playlist_id, song_id, position) VALUES (1, 1, 1);
+            INSERT INTO plays (song_id, played_at, listened_ms) VALUES (1, 0, 1000);",
+        )
+        .unwrap();
+
+        migrate(&mut conn).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(query_u32(&conn, "SELECT COUNT(*) FROM songs"), 1);
+        let song_path: String = conn
+            .query_row("SELECT song_path FROM songs WHERE song_id = 2", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(song_path, canonical.to_string_lossy());
+        assert_eq!(
+            query_u32(&conn, "SELECT song_id FROM playlist_song_link"),
+            2
+        );
+        assert_eq!(query_u32(&conn, "SELECT song_id FROM plays"), 2);
+        assert_eq!(
+            query_u32(&conn, "SELECT play_count FROM songs WHERE song_id = 2"),
+            2
+        );
+    }
+
+    #[test]
+    fn baseline_libraries_migrate_to_the_latest_schema() {
+        let dir = env::temp_dir().join(format!("bz_player_baseline_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = fs::canonicalize(dir).unwrap();
+        for name in ["a.mp3", "b.mp3"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+        let db_path = dir.join("library.db");
+
+        // The tables the first build created, with no user_version set
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE songs(
+                song_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                song_name TEXT UNIQUE NOT NULL,
+                song_path TEXT NOT NULL
+            );
+            CREATE TABLE playlists(
+                playlist_id INTEGER PRIMARY KEY AUTOINCREMENT,
+                playlist_name TEXT UNQIUE NOT NULL
+            );
+            CREATE TABLE playlist_song_link(
+                playlist_id INTEGER,
+                song_id INTEGER,
+                PRIMARY KEY (playlist_id, song_id),
+                FOREIGN KEY (playlist_id) REFERENCES playlists(playlist_id) ON DELETE CASCADE,
+                FOREIGN KEY (song_id) REFERENCES songs(song_id) ON DELETE CASCADE
+            );",
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO songs (song_id, song_name, song_path) VALUES
+                (1, 'a.mp3', ?1), (2, 'a copy.mp3', ?1), (3, 'b.mp3', ?2)",
+            params![
+                dir.join("a.mp3").to_string_lossy(),
+                dir.join("b.mp3").to_string_lossy()
+            ],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "INSERT INTO playlists (playlist_id, playlist_name) VALUES (1, 'Mix'), (2, 'Mix');
+            INSERT INTO playlist_song_link (playlist_id, song_id) VALUES (1, 2), (1, 3), (2, 1);",
+        )
+        .unwrap();
+        drop(conn);
+
+        let (sender, _receiver) = mpsc::channel();
+        let song_base = SongBase::init(&db_path, sender).unwrap();
+        let backed_up = dir.join("library.db.v0.bak").exists();
+        let report = song_base.check_library(false).unwrap();
+        let playlists = song_base.get_playlists().unwrap();
+        let mix: Vec<_> = song_base
+            .get_playlist(1)
+            .unwrap()
+            .songs
+            .into_iter()
+            .map(|song| (song.song_id, song.song_name))
+            .collect();
+        drop(song_base);
+        let version = current_version(&Connection::open(&db_path).unwrap()).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(version, latest_version());
+        assert!(backed_up);
+        assert!(report.issues.is_empty(), "{:?}", report.issues);
+        assert_eq!(
+            playlists,
+            [
+                (1, "Mix".to_string(), None),
+                (2, "Mix (2)".to_string(), None)
+            ]
+        );
+        // The song sharing a path folded into the first, keeping its place
+        assert_eq!(mix, [(1, "a.mp3".to_string()), (3, "b.mp3".to_string())]);
+    }
+}
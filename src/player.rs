@@ -1,50 +1,132 @@
-use crate::error::PlayerError;
+use crate::error::{PlayerError, SongBaseError};
 use crate::song::{Playlist, Song};
+use crate::song_base::{DupeGroup, ScanProgress, ScanSummary};
 use rodio::{self, OutputStream, Sink};
-use std::{fmt::Display, sync::mpsc::Sender};
+use std::{
+    fmt::Display,
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
 
 pub struct Player {
     queue: Vec<Song>,
     current_song: u32,
     sink: Sink,
-    _output_stream: OutputStream,
+    output_stream: Option<OutputStream>,
     communicater: Sender<PlayerAction>,
+    stop_after: bool,
+    /// Whether a track was put in the sink and hasn't been seen finishing.
+    loaded: bool,
+    listened: ListenTimer,
+    /// Whether the current track's play or skip was sent already.
+    reported: bool,
+}
+
+/// How long the current track has actually been playing, pauses left out.
+#[derive(Default)]
+struct ListenTimer {
+    resumed: Option<Instant>,
+    before: Duration,
+}
+
+impl ListenTimer {
+    fn restart(&mut self, playing: bool) {
+        self.before = Duration::ZERO;
+        self.resumed = playing.then(Instant::now);
+    }
+
+    fn pause(&mut self) {
+        if let Some(resumed) = self.resumed.take() {
+            self.before += resumed.elapsed();
+        }
+    }
+
+    fn resume(&mut self) {
+        self.resumed.get_or_insert_with(Instant::now);
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.before
+            + self
+                .resumed
+                .map_or(Duration::ZERO, |resumed| resumed.elapsed())
+    }
 }
 
 #[derive(Debug)]
 pub enum PlayerAction {
     ConnectionMessage(String),
+    ScanProgress(ScanProgress),
+    ScanFinished(Result<ScanSummary, SongBaseError>),
+    DupesFound(Result<Vec<DupeGroup>, SongBaseError>),
+    /// Song id and how long it was listened to, in ms.
+    TrackPlayed(u32, u64),
+    TrackSkipped(u32),
 }
 
 impl Display for PlayerAction {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::ConnectionMessage(message) => write!(f, "{}", message),
+            Self::ScanProgress(progress) => write!(
+                f,
+                "Scanning {}: {} Files",
+                progress.current_dir.display(),
+                progress.files_seen
+            ),
+            Self::ScanFinished(Ok(summary)) => write!(f, "{}", summary),
+            Self::ScanFinished(Err(err)) => write!(f, "{}", err),
+            Self::DupesFound(Ok(groups)) => write!(f, "Found {} Duplicate Groups", groups.len()),
+            Self::DupesFound(Err(err)) => write!(f, "{}", err),
+            Self::TrackPlayed(song_id, listened_ms) => {
+                write!(f, "Played {} for {}ms", song_id, listened_ms)
+            }
+            Self::TrackSkipped(song_id) => write!(f, "Skipped {}", song_id),
         }
     }
 }
 
 impl Player {
-    pub fn new(sender: Sender<PlayerAction>) -> Self {
-        let (_output_stream, output_stream_handle) = OutputStream::try_default().unwrap();
-        let queue = Vec::new();
-        let sender = sender;
+    pub fn new(sender: Sender<PlayerAction>) -> Result<Self, PlayerError> {
+        let (output_stream, output_stream_handle) = OutputStream::try_default()
+            .map_err(|err| PlayerError::AudioUnavailable(err.to_string()))?;
+
+        let sink = Sink::try_new(&output_stream_handle)
+            .map_err(|err| PlayerError::AudioUnavailable(err.to_string()))?;
+
+        Ok(Self::with_sink(sink, Some(output_stream), sender))
+    }
 
-        let sink = Sink::try_new(&output_stream_handle).unwrap();
+    /// A player that isn't attached to any audio device, so the queue and
+    /// the library stay usable when no output could be opened. Anything that
+    /// would load a track fails with `AudioUnavailable`.
+    pub fn new_idle(sender: Sender<PlayerAction>) -> Self {
+        let (sink, _) = Sink::new_idle();
+        Self::with_sink(sink, None, sender)
+    }
 
+    fn with_sink(
+        sink: Sink,
+        output_stream: Option<OutputStream>,
+        sender: Sender<PlayerAction>,
+    ) -> Self {
         Self {
-            queue,
+            queue: Vec::new(),
             current_song: 0,
-            _output_stream,
+            output_stream,
             sink,
             communicater: sender,
+            stop_after: false,
+            loaded: false,
+            listened: ListenTimer::default(),
+            reported: true,
         }
     }
 
     pub fn add_track(&mut self, song: Song) -> Result<u32, PlayerError> {
         self.queue.push(song);
         if self.sink.empty() {
-            if self.current_song != 0 {
+            if self.queue.len() > 1 {
                 return self.next_track();
             } else {
                 return self.play(true);
@@ -54,6 +136,7 @@ impl Player {
     }
 
     pub fn clear_tracks(&mut self) {
+        self.end_track(false);
         self.queue.clear();
     }
 
@@ -61,14 +144,16 @@ impl Player {
         if song_id == 0 || song_id > self.queue.len() {
             return Err(PlayerError::IndexOutOfBounds);
         }
+        self.end_track(false);
 
         let removed_song = self.queue.remove(song_id - 1);
 
         if self.queue.is_empty() {
             self.sink.clear();
+            self.loaded = false;
             return Ok(format!(
                 "Removed {} from queue! It's Empty Now!",
-                removed_song.song_name
+                removed_song.display_name()
             ));
         }
 
@@ -79,7 +164,7 @@ impl Player {
         self.play(true).map(|index| {
             format!(
                 "Removed {}, Now Playing {} @ {}",
-                removed_song.song_name,
+                removed_song.display_name(),
                 self.current_song_name(),
                 index
             )
@@ -94,49 +179,145 @@ impl Player {
         Ok(self.queue.len() as u32)
     }
 
-    pub fn play(&self, forced: bool) -> Result<u32, PlayerError> {
+    fn check_output(&self) -> Result<(), PlayerError> {
+        match self.output_stream {
+            Some(_) => Ok(()),
+            None => Err(PlayerError::AudioUnavailable(
+                "Started Without an Output Device".to_string(),
+            )),
+        }
+    }
+
+    pub fn play(&mut self, forced: bool) -> Result<u32, PlayerError> {
         if self.queue.is_empty() {
             return Err(PlayerError::EmptyQueue);
         }
+        self.check_output()?;
         if forced {
+            self.end_track(false);
             let song = self.queue.get(self.current_song as usize).unwrap();
             self.sink.clear();
             self.sink.append(song.get_source().unwrap());
             self.sink.play();
+            self.loaded = true;
+            self.listened.restart(true);
+            self.reported = false;
         } else {
             self.sink.play();
+            self.listened.resume();
         }
         Ok(self.current_song)
     }
 
-    pub fn toggle_player(&self) -> String {
+    pub fn toggle_player(&mut self) -> String {
         if self.sink.is_paused() {
             self.sink.play();
+            self.listened.resume();
             "player resumed".to_string()
         } else {
             self.sink.pause();
+            self.listened.pause();
             "player paused".to_string()
         }
     }
 
+    /// Halts playback and rewinds to the start of the current track.
+    pub fn stop(&mut self) -> Result<u32, PlayerError> {
+        self.check_output()?;
+        self.end_track(false);
+        let song = self
+            .queue
+            .get(self.current_song as usize)
+            .ok_or(PlayerError::EmptyQueue)?;
+        let source = song.get_source().map_err(PlayerError::SongError)?;
+        self.sink.clear();
+        self.sink.append(source);
+        self.sink.pause();
+        self.loaded = true;
+        self.listened.restart(false);
+        self.reported = false;
+        Ok(self.current_song)
+    }
+
+    /// Sends the current track's play once it has finished. Called every
+    /// frame, so tracks ending with nothing queued after them count too.
+    /// `true` only on the frame a loaded track runs out.
+    pub fn check_finished(&mut self) -> bool {
+        if !self.is_sink_empty() {
+            return false;
+        }
+        self.end_track(false);
+        std::mem::take(&mut self.loaded)
+    }
+
+    /// Reports how the current track went, once per playback: finished or
+    /// listened to past half it's a play, left early with `next` a skip.
+    fn end_track(&mut self, skipping: bool) {
+        if self.reported || self.output_stream.is_none() {
+            return;
+        }
+        let Some(song) = self.queue.get(self.current_song as usize) else {
+            return;
+        };
+
+        let listened_ms = self.listened.elapsed().as_millis() as u64;
+        let past_half = song
+            .tags
+            .duration_ms
+            .is_some_and(|duration_ms| listened_ms * 2 >= duration_ms);
+        let action = if self.sink.empty() || past_half {
+            PlayerAction::TrackPlayed(song.song_id, listened_ms)
+        } else if skipping {
+            PlayerAction::TrackSkipped(song.song_id)
+        } else {
+            return;
+        };
+        let _ = self.communicater.send(action);
+        self.reported = true;
+    }
+
+    pub fn toggle_stop_after(&mut self) -> bool {
+        self.stop_after = !self.stop_after;
+        self.stop_after
+    }
+
+    pub fn is_stop_after(&self) -> bool {
+        self.stop_after
+    }
+
+    /// Called once the current track has finished while stop-after is armed.
+    /// Disarms it and cues the next track (if any) without playing it.
+    pub fn fire_stop_after(&mut self) -> Result<u32, PlayerError> {
+        self.end_track(false);
+        self.stop_after = false;
+        if self.is_last() {
+            return Err(PlayerError::LastSong);
+        }
+        self.current_song += 1;
+        self.stop()
+    }
+
     pub fn next_track(&mut self) -> Result<u32, PlayerError> {
         if (self.current_song + 1) as usize >= self.queue.len() {
             Err(PlayerError::IndexOutOfBounds)
         } else {
+            self.end_track(true);
             self.current_song += 1;
             self.play(true)?;
             Ok(self.current_song)
         }
     }
 
-    pub fn pause(&self) {
+    pub fn pause(&mut self) {
         self.sink.pause();
+        self.listened.pause();
     }
 
     pub fn jump_track(&mut self, index: usize) -> Result<u32, PlayerError> {
         if index >= self.queue.len() {
             return Err(PlayerError::IndexOutOfBounds);
         }
+        self.end_track(false);
         self.current_song = index as u32;
         self.play(true)
     }
@@ -146,12 +327,12 @@ impl Player {
             Err(PlayerError::IndexOutOfBounds)
         } else {
             let song = self.queue.get(index).unwrap();
-            Ok(song.song_name.clone())
+            Ok(song.display_name())
         }
     }
 
-    pub fn get_queue(&self) -> Vec<&String> {
-        self.queue.iter().map(|song| &song.song_name).collect()
+    pub fn get_queue(&self) -> Vec<&Song> {
+        self.queue.iter().collect()
     }
 
     pub fn get_queue_ids(&self) -> Vec<u32> {
@@ -162,19 +343,35 @@ impl Player {
         self.current_song
     }
 
+    /// `None` when the queue is empty.
+    pub fn current_song_id(&self) -> Option<u32> {
+        self.queue
+            .get(self.current_song as usize)
+            .map(|song| song.song_id)
+    }
+
+    /// Applies a change made in the library to every queued copy of the song.
+    pub fn update_queued(&mut self, song_id: u32, update: impl Fn(&mut Song)) {
+        self.queue
+            .iter_mut()
+            .filter(|song| song.song_id == song_id)
+            .for_each(update);
+    }
+
     pub fn current_song_name(&self) -> String {
         self.queue
             .get(self.current_song as usize)
             .unwrap()
-            .song_name
-            .clone()
+            .display_name()
     }
 
+    /// Whether the current track is done. An idle player never finishes a
+    /// track, so the queue isn't auto-advanced without an output device.
     pub fn is_sink_empty(&self) -> bool {
-        self.sink.empty()
+        self.output_stream.is_some() && self.sink.empty()
     }
 
     pub fn is_last(&self) -> bool {
-        (self.current_song) as usize == self.queue.len()
+        (self.current_song + 1) as usize >= self.queue.len()
     }
 }
@@ -0,0 +1,601 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+/// A parsed search like `artist:queen year:>1975 -live dur:<5m`.
+///
+/// Bare words are matched against the full-text index, `field:value` terms
+/// filter on a single column, a leading `-` negates a term and `@name`
+/// pulls in a saved filter. `label:workout` keeps the songs carrying a
+/// label. `sort:plays` (or `sort:-plays`, descending) orders the results
+/// and `limit:100` keeps only the first of them.
+#[derive(Debug, PartialEq, Default)]
+pub struct Query {
+    pub terms: Vec<Term>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Term {
+    pub negated: bool,
+    pub kind: TermKind,
+    /// Where the term starts in the query, for error messages.
+    pub position: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TermKind {
+    Word(String),
+    Text(TextField, String),
+    Number(NumberField, Comparison),
+    Saved(String),
+    Label(String),
+    /// Descending when the flag is set.
+    Sort(SortField, bool),
+    Limit(usize),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TextField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Path,
+    FileName,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum NumberField {
+    Year,
+    Track,
+    Disc,
+    Duration,
+    Plays,
+    Skips,
+    Rating,
+    /// Whole days since the song was last played.
+    DaysSincePlayed,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SortField {
+    Title,
+    Artist,
+    Album,
+    Year,
+    Duration,
+    Plays,
+    Skips,
+    LastPlayed,
+    Rating,
+    Random,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Comparison {
+    Equal(i64),
+    Greater(i64),
+    GreaterOrEqual(i64),
+    Less(i64),
+    LessOrEqual(i64),
+    Between(i64, i64),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QueryError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{} (at {})", self.message, self.position + 1)
+    }
+}
+
+impl TextField {
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::Title => "songs.title",
+            Self::Artist => "songs.artist",
+            Self::Album => "songs.album",
+            Self::AlbumArtist => "songs.album_artist",
+            Self::Genre => "songs.genre",
+            Self::Path => "songs.song_path",
+            Self::FileName => "songs.song_name",
+        }
+    }
+}
+
+impl NumberField {
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::Year => "songs.year",
+            Self::Track => "songs.track_number",
+            Self::Disc => "songs.disc_number",
+            Self::Duration => "songs.duration_ms",
+            Self::Plays => "songs.play_count",
+            Self::Skips => "songs.skip_count",
+            Self::Rating => "songs.rating",
+            Self::DaysSincePlayed => {
+                "(CAST(strftime('%s', 'now') AS INTEGER) - songs.last_played) / 86400"
+            }
+        }
+    }
+}
+
+impl SortField {
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::Title => "songs.title",
+            Self::Artist => "songs.artist",
+            Self::Album => "songs.album",
+            Self::Year => "songs.year",
+            Self::Duration => "songs.duration_ms",
+            Self::Plays => "songs.play_count",
+            Self::Skips => "songs.skip_count",
+            Self::LastPlayed => "songs.last_played",
+            Self::Rating => "songs.rating",
+            Self::Random => "RANDOM()",
+        }
+    }
+
+    /// How the sorted on value is shown next to each result, for the play
+    /// statistics the display name doesn't tell.
+    pub fn detail_sql(&self) -> Option<&'static str> {
+        match self {
+            Self::Plays => Some(
+                "songs.play_count || CASE songs.play_count WHEN 1 THEN ' play' ELSE ' plays' END",
+            ),
+            Self::Skips => Some(
+                "songs.skip_count || CASE songs.skip_count WHEN 1 THEN ' skip' ELSE ' skips' END",
+            ),
+            Self::LastPlayed => Some(
+                "COALESCE(strftime('%Y-%m-%d %H:%M', songs.last_played, 'unixepoch', 'localtime'),
+                    'never played')",
+            ),
+            _ => None,
+        }
+    }
+}
+
+impl Comparison {
+    /// SQL comparing `column` against `?` placeholders, plus their values.
+    pub fn to_sql(self, column: &str) -> (String, Vec<i64>) {
+        match self {
+            Self::Equal(value) => (format!("{} = ?", column), vec![value]),
+            Self::Greater(value) => (format!("{} > ?", column), vec![value]),
+            Self::GreaterOrEqual(value) => (format!("{} >= ?", column), vec![value]),
+            Self::Less(value) => (format!("{} < ?", column), vec![value]),
+            Self::LessOrEqual(value) => (format!("{} <= ?", column), vec![value]),
+            Self::Between(low, high) => (format!("{} BETWEEN ? AND ?", column), vec![low, high]),
+        }
+    }
+}
+
+const FIELDS: &str =
+    "title, artist, album, albumartist, genre, path, file, year, track, disc, dur, plays, skips, rating, played, label, sort, limit";
+const SORT_FIELDS: &str = "title, artist, album, year, dur, plays, skips, played, rating, random";
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self, QueryError> {
+        let terms = tokenize(text)?
+            .into_iter()
+            .map(|(position, token)| parse_term(position, &token))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { terms })
+    }
+
+    /// Nothing but words, so the plain search (with its fuzzy fallback) fits.
+    pub fn is_plain(&self) -> bool {
+        self.terms
+            .iter()
+            .all(|term| !term.negated && matches!(term.kind, TermKind::Word(_)))
+    }
+
+    /// What to order by, in the order given.
+    pub fn sorts(&self) -> Vec<(SortField, bool)> {
+        self.terms
+            .iter()
+            .filter_map(|term| match term.kind {
+                TermKind::Sort(field, descending) => Some((field, descending)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// How many results to keep at most, the last `limit:` wins.
+    pub fn limit(&self) -> Option<usize> {
+        self.terms.iter().rev().find_map(|term| match term.kind {
+            TermKind::Limit(limit) => Some(limit),
+            _ => None,
+        })
+    }
+
+    /// The words that have to match, joined back together.
+    pub fn words(&self) -> String {
+        self.terms
+            .iter()
+            .filter_map(|term| match &term.kind {
+                TermKind::Word(word) if !term.negated => Some(word.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Splits on whitespace, keeping `"quoted values"` together.
+fn tokenize(text: &str) -> Result<Vec<(usize, String)>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    let mut quote_start = None;
+
+    for (position, ch) in text.chars().enumerate() {
+        match ch {
+            '"' => {
+                quote_start = match quote_start {
+                    Some(_) => None,
+                    None => Some(position),
+                };
+                current.get_or_insert((position, String::new()));
+            }
+            ch if ch.is_whitespace() && quote_start.is_none() => {
+                if let Some(token) = current.take() {
+                    tokens.push(token);
+                }
+            }
+            ch => current.get_or_insert((position, String::new())).1.push(ch),
+        }
+    }
+
+    if let Some(position) = quote_start {
+        return Err(QueryError {
+            position,
+            message: "unclosed quote".to_string(),
+        });
+    }
+    if let Some(token) = current.take() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_term(position: usize, token: &str) -> Result<Term, QueryError> {
+    let error = |offset: usize, message: String| QueryError {
+        position: position + offset,
+        message,
+    };
+
+    let (negated, body, offset) = match token.strip_prefix('-') {
+        Some(body) if !body.is_empty() => (true, body, 1),
+        _ => (false, token, 0),
+    };
+
+    let kind = if let Some(name) = body.strip_prefix('@') {
+        if name.is_empty() {
+            return Err(error(offset, "missing filter name after '@'".to_string()));
+        }
+        TermKind::Saved(name.to_lowercase())
+    } else if let Some((field, value)) = body.split_once(':') {
+        let value_offset = offset + field.chars().count() + 1;
+        if value.is_empty() {
+            return Err(error(
+                value_offset,
+                format!("missing value after '{}:'", field),
+            ));
+        }
+
+        let field = field.to_lowercase();
+        if field == "sort" {
+            if negated {
+                return Err(error(0, "a sort can't be negated".to_string()));
+            }
+            return parse_sort(value)
+                .map_err(|message| error(value_offset, message))
+                .map(|(sort_field, descending)| Term {
+                    negated,
+                    kind: TermKind::Sort(sort_field, descending),
+                    position,
+                });
+        }
+
+        if field == "limit" {
+            if negated {
+                return Err(error(0, "a limit can't be negated".to_string()));
+            }
+            let limit = value
+                .parse()
+                .map_err(|_| error(value_offset, format!("'{}' isn't a number", value)))?;
+            return Ok(Term {
+                negated,
+                kind: TermKind::Limit(limit),
+                position,
+            });
+        }
+
+        if field == "label" {
+            return Ok(Term {
+                negated,
+                kind: TermKind::Label(value.to_lowercase()),
+                position,
+            });
+        }
+
+        let text_field = match field.as_str() {
+            "title" => Some(TextField::Title),
+            "artist" => Some(TextField::Artist),
+            "album" => Some(TextField::Album),
+            "albumartist" => Some(TextField::AlbumArtist),
+            "genre" => Some(TextField::Genre),
+            "path" => Some(TextField::Path),
+            "file" | "name" => Some(TextField::FileName),
+            _ => None,
+        };
+        let number_field = match field.as_str() {
+            "year" => Some(NumberField::Year),
+            "track" => Some(NumberField::Track),
+            "disc" => Some(NumberField::Disc),
+            "dur" | "duration" | "length" => Some(NumberField::Duration),
+            "plays" => Some(NumberField::Plays),
+            "skips" => Some(NumberField::Skips),
+            "rating" | "stars" => Some(NumberField::Rating),
+            "played" | "lastplayed" => Some(NumberField::DaysSincePlayed),
+            _ => None,
+        };
+
+        match (text_field, number_field) {
+            (Some(text_field), _) => TermKind::Text(text_field, value.to_string()),
+            (None, Some(number_field)) => {
+                let comparison = parse_comparison(number_field, value)
+                    .map_err(|message| error(value_offset, message))?;
+                TermKind::Number(number_field, comparison)
+            }
+            (None, None) => {
+                return Err(error(
+                    offset,
+                    format!("unknown field '{}', try one of: {}", field, FIELDS),
+                ))
+            }
+        }
+    } else {
+        TermKind::Word(body.to_string())
+    };
+
+    Ok(Term {
+        negated,
+        kind,
+        position,
+    })
+}
+
+/// `plays` ascending, `-plays` descending.
+fn parse_sort(value: &str) -> Result<(SortField, bool), String> {
+    let (descending, name) = match value.strip_prefix('-') {
+        Some(name) => (true, name),
+        None => (false, value),
+    };
+    let field = match name.to_lowercase().as_str() {
+        "title" => SortField::Title,
+        "artist" => SortField::Artist,
+        "album" => SortField::Album,
+        "year" => SortField::Year,
+        "dur" | "duration" | "length" => SortField::Duration,
+        "plays" => SortField::Plays,
+        "skips" => SortField::Skips,
+        "played" | "lastplayed" => SortField::LastPlayed,
+        "rating" | "stars" => SortField::Rating,
+        "random" | "shuffle" => SortField::Random,
+        _ => {
+            return Err(format!(
+                "can't sort by '{}', try one of: {}",
+                name, SORT_FIELDS
+            ))
+        }
+    };
+    Ok((field, descending))
+}
+
+fn parse_comparison(field: NumberField, value: &str) -> Result<Comparison, String> {
+    let parse = |value: &str| match field {
+        NumberField::Duration => parse_duration_ms(value)
+            .ok_or_else(|| format!("'{}' isn't a duration like 90s, 5m or 3:30", value)),
+        NumberField::DaysSincePlayed => value
+            .strip_suffix('d')
+            .unwrap_or(value)
+            .parse::<i64>()
+            .map_err(|_| format!("'{}' isn't a number of days", value)),
+        _ => value
+            .parse::<i64>()
+            .map_err(|_| format!("'{}' isn't a number", value)),
+    };
+
+    if let Some((low, high)) = value.split_once("..") {
+        return Ok(Comparison::Between(parse(low)?, parse(high)?));
+    }
+
+    let comparison = if let Some(value) = value.strip_prefix(">=") {
+        Comparison::GreaterOrEqual(parse(value)?)
+    } else if let Some(value) = value.strip_prefix("<=") {
+        Comparison::LessOrEqual(parse(value)?)
+    } else if let Some(value) = value.strip_prefix('>') {
+        Comparison::Greater(parse(value)?)
+    } else if let Some(value) = value.strip_prefix('<') {
+        Comparison::Less(parse(value)?)
+    } else {
+        Comparison::Equal(parse(value.strip_prefix('=').unwrap_or(value))?)
+    };
+    Ok(comparison)
+}
+
+/// `90s`, `5m`, `3m30s`, `1h`, `3:30` or plain seconds, in milliseconds.
+fn parse_duration_ms(value: &str) -> Option<i64> {
+    if value.is_empty() {
+        return None;
+    }
+
+    if value.contains(':') {
+        return value
+            .split(':')
+            .try_fold(0, |total, part| {
+                Some(total * 60 + part.parse::<i64>().ok()?)
+            })
+            .map(|seconds| seconds * 1000);
+    }
+
+    let mut seconds = 0;
+    let mut number = String::new();
+    for ch in value.chars() {
+        match ch {
+            '0'..='9' => number.push(ch),
+            'h' | 'm' | 's' => {
+                let unit = match ch {
+                    'h' => 3600,
+                    'm' => 60,
+                    _ => 1,
+                };
+                seconds += number.parse::<i64>().ok()? * unit;
+                number.clear();
+            }
+            _ => return None,
+        }
+    }
+    if !number.is_empty() {
+        seconds += number.parse::<i64>().ok()?;
+    }
+    Some(seconds * 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<(bool, TermKind)> {
+        Query::parse(text)
+            .unwrap()
+            .terms
+            .into_iter()
+            .map(|term| (term.negated, term.kind))
+            .collect()
+    }
+
+    fn error(text: &str) -> (usize, String) {
+        let err = Query::parse(text).unwrap_err();
+        (err.position, err.message)
+    }
+
+    #[test]
+    fn words_fields_and_negation() {
+        assert_eq!(
+            kinds("artist:queen year:>1975 -live -genre:\"hard rock\" @Gym"),
+            [
+                (
+                    false,
+                    TermKind::Text(TextField::Artist, "queen".to_string())
+                ),
+                (
+                    false,
+                    TermKind::Number(NumberField::Year, Comparison::Greater(1975))
+                ),
+                (true, TermKind::Word("live".to_string())),
+                (
+                    true,
+                    TermKind::Text(TextField::Genre, "hard rock".to_string())
+                ),
+                (false, TermKind::Saved("gym".to_string())),
+            ]
+        );
+
+        // A lone dash is a word, not an empty negation
+        assert_eq!(kinds("-"), [(false, TermKind::Word("-".to_string()))]);
+    }
+
+    #[test]
+    fn plain_queries_keep_the_fuzzy_search() {
+        assert!(Query::parse("bohemian rhapsody").unwrap().is_plain());
+        assert!(!Query::parse("bohemian -live").unwrap().is_plain());
+        assert!(!Query::parse("bohemian year:1975").unwrap().is_plain());
+        assert_eq!(
+            Query::parse("queen -live year:1975 rhapsody")
+                .unwrap()
+                .words(),
+            "queen rhapsody"
+        );
+    }
+
+    #[test]
+    fn comparisons_and_ranges() {
+        let number = |text: &str| match kinds(text).pop() {
+            Some((_, TermKind::Number(_, comparison))) => comparison,
+            other => panic!("{:?} isn't a number term", other),
+        };
+        assert_eq!(number("year:1990..1999"), Comparison::Between(1990, 1999));
+        assert_eq!(number("track:>=3"), Comparison::GreaterOrEqual(3));
+        assert_eq!(number("disc:<=2"), Comparison::LessOrEqual(2));
+        assert_eq!(number("year:=1975"), Comparison::Equal(1975));
+        assert_eq!(number("dur:<5m"), Comparison::Less(300_000));
+        assert_eq!(
+            number("dur:3:00..4:30"),
+            Comparison::Between(180_000, 270_000)
+        );
+    }
+
+    #[test]
+    fn durations_in_milliseconds() {
+        assert_eq!(parse_duration_ms("90s"), Some(90_000));
+        assert_eq!(parse_duration_ms("3m30s"), Some(210_000));
+        assert_eq!(parse_duration_ms("3:30"), Some(210_000));
+        assert_eq!(parse_duration_ms("1h"), Some(3_600_000));
+        assert_eq!(parse_duration_ms("45"), Some(45_000));
+        assert_eq!(parse_duration_ms(""), None);
+        assert_eq!(parse_duration_ms("5x"), None);
+        assert_eq!(parse_duration_ms("3:x"), None);
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_part() {
+        assert_eq!(error("queen \"live at"), (6, "unclosed quote".to_string()));
+        assert_eq!(
+            error("queen artist:"),
+            (13, "missing value after 'artist:'".to_string())
+        );
+        assert_eq!(
+            error("-@"),
+            (1, "missing filter name after '@'".to_string())
+        );
+        assert_eq!(error("year:abc"), (5, "'abc' isn't a number".to_string()));
+        let (position, message) = error("x -colour:red");
+        assert_eq!(position, 3);
+        assert!(message.starts_with("unknown field 'colour'"));
+    }
+
+    #[test]
+    fn sorts_keep_their_order_and_the_last_limit_wins() {
+        let query = Query::parse("sort:-plays sort:Random limit:10 played:>30d limit:3").unwrap();
+        assert_eq!(
+            query.sorts(),
+            [(SortField::Plays, true), (SortField::Random, false)]
+        );
+        assert_eq!(query.limit(), Some(3));
+        assert!(query.terms.contains(&Term {
+            negated: false,
+            kind: TermKind::Number(NumberField::DaysSincePlayed, Comparison::Greater(30)),
+            position: 33,
+        }));
+        assert_eq!(Query::parse("queen").unwrap().limit(), None);
+        assert_eq!(
+            kinds("sort:shuffle"),
+            [(false, TermKind::Sort(SortField::Random, false))]
+        );
+
+        assert_eq!(
+            error("-sort:plays"),
+            (0, "a sort can't be negated".to_string())
+        );
+        assert_eq!(
+            error("a -limit:5"),
+            (2, "a limit can't be negated".to_string())
+        );
+        assert_eq!(error("limit:-1"), (6, "'-1' isn't a number".to_string()));
+        assert!(error("sort:colour").1.starts_with("can't sort by 'colour'"));
+    }
+}
@@ -6,13 +6,16 @@ use std::{
 
 use rodio::Decoder;
 
-use crate::error::SongError;
+use crate::{error::SongError, tags::Tags};
 
 #[derive(Debug)]
 pub enum Playable {
     SongByName(Vec<String>),
     SongById(Vec<u32>),
     Playlist(u8),
+    Album(u32),
+    Favorites,
+    Label(String),
     None,
 }
 
@@ -24,8 +27,22 @@ impl std::fmt::Display for PlaylistActions {
             PlaylistActions::Create(playlist_name, path) => {
                 write!(f, "Playlist Create {:?} {:?}", playlist_name, path)
             }
+            PlaylistActions::CreateSmart(playlist_name, rule) => {
+                write!(f, "Playlist Create {:?} -r {:?}", playlist_name, rule)
+            }
             PlaylistActions::Add(id, songs) => write!(f, "Playlist Add {:?} {:?}", id, songs),
             PlaylistActions::AddAll(id) => write!(f, "Playlist add * {:?}", id),
+            PlaylistActions::Remove(id, positions) => {
+                write!(f, "Playlist Remove {:?} {:?}", id, positions)
+            }
+            PlaylistActions::Move(id, from, to) => {
+                write!(f, "Playlist Move {:?} {:?} {:?}", id, from, to)
+            }
+            PlaylistActions::Rename(id, name) => write!(f, "Playlist Rename {:?} {:?}", id, name),
+            PlaylistActions::Delete(id) => write!(f, "Playlist Delete {:?}", id),
+            PlaylistActions::Duplicate(id, name) => write!(f, "Playlist Dup {:?} {:?}", id, name),
+            PlaylistActions::Import(path) => write!(f, "Playlist Import {:?}", path),
+            PlaylistActions::Export(id, path) => write!(f, "Playlist Export {:?} {:?}", id, path),
             PlaylistActions::Invalid => write!(f, "Playlist Invalid"),
         }
     }
@@ -36,8 +53,69 @@ pub enum PlaylistActions {
     Show,
     View(Option<u8>),
     Create(Option<String>, Option<PathBuf>),
+    /// Name and rule.
+    CreateSmart(String, String),
     Add(u8, Option<Vec<String>>),
     AddAll(Option<u8>),
+    /// 1-based positions to take out.
+    Remove(u8, Vec<usize>),
+    /// From and to, 1-based.
+    Move(u8, usize, usize),
+    Rename(u8, String),
+    Delete(u8),
+    /// The copy's name, `<name> (copy)` by default.
+    Duplicate(u8, Option<String>),
+    /// An M3U or M3U8 file.
+    Import(PathBuf),
+    Export(u8, PathBuf),
+    Invalid,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum LibraryActions {
+    Check(bool),
+    Dupes,
+    /// Group number, and which copy to keep (the suggested one by default).
+    Merge(usize, Option<usize>),
+    Invalid,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ScanActions {
+    Exclude(String),
+    Include(String),
+    Depth(Option<usize>),
+    /// Whether hidden folders get scanned.
+    Hidden(bool),
+    Cancel,
+    Invalid,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum LabelActions {
+    /// Label and song names, `None` labels the whole queue.
+    Add(String, Option<Vec<String>>),
+    /// Label and song names, `None` drops the label altogether.
+    Remove(String, Option<Vec<String>>),
+    Invalid,
+}
+
+/// A missing song name means the one playing.
+#[derive(PartialEq, Debug)]
+pub enum RatingActions {
+    /// Stars, `None` clears the rating.
+    Rate(Option<u8>, Option<String>),
+    Favorite(bool, Option<String>),
+    /// Whether ratings get written into the files' tags.
+    WriteTags(bool),
+    Invalid,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum FilterActions {
+    /// Name and query, `None` saves the search that's on screen.
+    Save(String, Option<String>),
+    Remove(String),
     Invalid,
 }
 
@@ -46,6 +124,8 @@ pub struct Song {
     pub song_id: u32,
     pub song_name: String,
     pub song_path: PathBuf,
+    pub tags: Tags,
+    pub favorite: bool,
 }
 
 impl Song {
@@ -67,9 +147,25 @@ impl Song {
             song_id: id,
             song_name: song_name.to_string(),
             song_path: path_check,
+            tags: Tags::default(),
+            favorite: false,
         })
     }
 
+    pub fn with_tags(mut self, tags: Tags) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    /// "Artist - Title" when tagged, the file name otherwise.
+    pub fn display_name(&self) -> String {
+        match (&self.tags.artist, &self.tags.title) {
+            (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+            (None, Some(title)) => title.clone(),
+            _ => self.song_name.clone(),
+        }
+    }
+
     pub fn get_source(&self) -> Result<Decoder<BufReader<File>>, SongError> {
         let file = File::open(self.song_path.as_path());
         if file.is_err() {
@@ -80,11 +176,12 @@ impl Song {
         Ok(Decoder::new(reader).unwrap())
     }
 
+    pub const SUPPORTED_EXTENSIONS: [&'static str; 5] = ["mp3", "ogg", "wav", "flac", "m4a"];
+
     pub fn is_valid_song_path(path: &Path) -> bool {
-        match path.extension().and_then(|ext| ext.to_str()) {
-            Some("mp3") | Some("ogg") | Some("wav") => true,
-            _ => false,
-        }
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| Self::SUPPORTED_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
     }
 }
 
@@ -92,6 +189,8 @@ impl Song {
 pub struct Playlist {
     pub playlist_name: String,
     pub songs: Vec<Song>,
+    /// The search a smart playlist is filled from.
+    pub rule: Option<String>,
 }
 
 impl Playlist {
@@ -99,6 +198,7 @@ impl Playlist {
         Self {
             playlist_name: playlist_name.to_string(),
             songs: Vec::new(),
+            rule: None,
         }
     }
 
@@ -106,3 +206,43 @@ impl Playlist {
         self.songs.push(song)
     }
 }
+
+#[derive(Debug, PartialEq)]
+pub struct ArtistSummary {
+    pub artist: String,
+    pub albums: u32,
+    pub tracks: u32,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AlbumSummary {
+    pub album_id: u32,
+    pub album_name: String,
+    pub album_artist: String,
+    pub year: Option<u32>,
+    pub tracks: u32,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SearchHit {
+    pub song_id: u32,
+    pub display_name: String,
+    /// Set when the song only matched through typo tolerance.
+    pub fuzzy_score: Option<u8>,
+    /// The value a search is sorted on, when it's worth showing.
+    pub detail: Option<String>,
+    pub rating: Option<u8>,
+    pub favorite: bool,
+}
+
+/// One entry of the listening history.
+#[derive(Debug, PartialEq)]
+pub struct PlayRecord {
+    pub song_id: u32,
+    pub display_name: String,
+    /// Local time, "2024-06-01 21:04".
+    pub played_at: String,
+    pub listened_ms: u64,
+}
@@ -1,27 +1,77 @@
 use std::{
+    fs,
     ops::{Deref, DerefMut},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{mpsc::Sender, Arc, Mutex},
-    thread,
 };
 
 use crate::{
     error::{SongBaseError, SongError},
+    migrations,
     player::PlayerAction,
-    song::{Playlist, Song},
+    song::{AlbumSummary, ArtistSummary, Playlist, SearchHit, Song},
+    tags::Tags,
 };
-use rusqlite::{Connection, Error as rusqliteError, ErrorCode};
+use notify::RecommendedWatcher;
+use rusqlite::{params, Connection, Error as rusqliteError, ErrorCode, OptionalExtension, Row};
+
+mod dupes;
+mod filters;
+mod fuzzy;
+mod integrity;
+mod labels;
+mod playlists;
+mod plays;
+mod progress;
+mod ratings;
+mod scan;
+mod stats;
+mod walk;
+mod watch;
+
+pub use dupes::DupeGroup;
+pub use integrity::{IntegrityReport, IssueKind};
+pub use progress::{ScanJob, ScanProgress};
+use scan::FileStamp;
+pub use scan::ScanSummary;
+pub use stats::{ListeningStats, StatsPeriod};
+pub use walk::ScanOptions;
 
 pub struct SongBase {
     conn: Arc<Mutex<Connection>>,
     sender: Sender<PlayerAction>,
+    /// Set once `watch_library` runs, dropping it stops the watching.
+    watcher: Mutex<Option<RecommendedWatcher>>,
 }
 
 impl SongBase {
-    const INSERT_SONG_QUERY: &'static str =
-        "INSERT INTO songs (song_name, song_path) VALUES (?1, ?2)";
-    pub fn init(db_name: &str, sender: Sender<PlayerAction>) -> Result<Self, SongBaseError> {
-        let conn = Connection::open(db_name).map_err(|err| {
+    /// Used as a fallback library when the database file can't be opened.
+    pub const IN_MEMORY: &'static str = ":memory:";
+
+    const INSERT_SONG_QUERY: &'static str = "INSERT INTO songs (song_name, song_path,
+        title, artist, album, album_artist, track_number, disc_number, year, genre, duration_ms,
+        file_mtime, file_size, rating)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)";
+    const UPDATE_TAGS_QUERY: &'static str = "UPDATE songs SET song_name = ?1,
+        title = ?3, artist = ?4, album = ?5, album_artist = ?6, track_number = ?7,
+        disc_number = ?8, year = ?9, genre = ?10, duration_ms = ?11,
+        file_mtime = ?12, file_size = ?13, missing = 0, content_hash = NULL, fingerprint = NULL,
+        rating = CASE WHEN rating_set THEN rating ELSE COALESCE(?14, rating) END
+        WHERE song_path = ?2";
+
+    /// Songs that show up in listings: not gone from disk and not merged
+    /// into another copy.
+    pub const LISTED_SQL: &'static str = "NOT songs.missing AND songs.merged_into IS NULL";
+
+    /// What a song is shown as, "Artist - Title" falling back to the file name.
+    pub const DISPLAY_NAME_SQL: &'static str =
+        "COALESCE(songs.artist || ' - ' || songs.title, songs.title, songs.song_name)";
+    pub fn init<P: AsRef<Path>>(
+        db_name: P,
+        sender: Sender<PlayerAction>,
+    ) -> Result<Self, SongBaseError> {
+        Self::create_folder(db_name.as_ref())?;
+        let mut conn = Connection::open(db_name).map_err(|err| {
             if err.sqlite_error_code() == Some(ErrorCode::CannotOpen) {
                 SongBaseError::AccessFailed
             } else {
@@ -29,69 +79,51 @@ impl SongBase {
             }
         })?;
 
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS songs(
-                song_id INTEGER PRIMARY KEY AUTOINCREMENT,
-                song_name TEXT UNIQUE NOT NULL,
-                song_path TEXT NOT NULL
-            )",
-            (),
-        )
-        .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS playlists(
-                playlist_id INTEGER PRIMARY KEY AUTOINCREMENT,
-                playlist_name TEXT UNQIUE NOT NULL
-            )",
-            (),
-        )
-        .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS playlist_song_link(
-                playlist_id INTEGER,
-                song_id INTEGER,
-                PRIMARY KEY (playlist_id, song_id),
-                FOREIGN KEY (playlist_id) REFERENCES playlists(playlist_id) ON DELETE CASCADE,
-                FOREIGN KEY (song_id) REFERENCES songs(song_id) ON DELETE CASCADE
-            )",
-            [],
-        )
-        .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
+        migrations::migrate(&mut conn)?;
+        fuzzy::register(&conn)?;
 
         let conn = Arc::new(Mutex::new(conn));
-        Ok(Self { conn, sender })
+        Ok(Self {
+            conn,
+            sender,
+            watcher: Mutex::new(None),
+        })
     }
 
-    pub fn find_song_by_name(&self, song_name: String) -> Result<Song, SongBaseError> {
-        let pattern = format!("%{}%", song_name);
-
-        let binding = self.conn.lock().unwrap();
-
-        let mut fetch_query = binding
-            .prepare(
-                "SELECT * FROM songs WHERE 
-            song_name LIKE ?1",
-            )
-            .unwrap();
+    /// Creates the folder the library file goes in, the data directory on
+    /// a first run.
+    pub fn create_folder(db_path: &Path) -> Result<(), SongBaseError> {
+        match db_path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => {
+                fs::create_dir_all(parent).map_err(|err| {
+                    SongBaseError::FolderFailed(format!("{}: {}", parent.display(), err))
+                })
+            }
+            _ => Ok(()),
+        }
+    }
 
-        //Contains all result
-        let mut fetch_result = fetch_query
-            .query([pattern])
-            .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
+    /// True when the library has neither songs nor playlists.
+    pub fn is_empty(&self) -> Result<bool, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        let count: u32 = connection.query_row(
+            "SELECT (SELECT COUNT(*) FROM songs) + (SELECT COUNT(*) FROM playlists)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count == 0)
+    }
 
-        //get the first row
-        let best_match = fetch_result
+    /// The best ranked song for the search term, falling back to the closest
+    /// fuzzy match.
+    pub fn find_song_by_name(&self, song_name: String) -> Result<Song, SongBaseError> {
+        let best_match = self
+            .filter_song(&song_name)?
+            .into_iter()
             .next()
-            .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?
             .ok_or(SongBaseError::EntryNotFound)?;
 
-        let song_id: u32 = best_match.get("song_id").unwrap();
-        let song_name: String = best_match.get("song_name").unwrap();
-        let song_path: String = best_match.get("song_path").unwrap();
-
-        Ok(Song::new(song_id, song_name, song_path).unwrap())
+        self.find_song_by_id(best_match.song_id)
     }
 
     pub fn find_song_by_id(&self, song_id: u32) -> Result<Song, SongBaseError> {
@@ -105,11 +137,7 @@ impl SongBase {
             .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
 
         let song = song_query
-            .query_row([song_id], |row| {
-                let song_name: String = row.get("song_name")?;
-                let song_path: String = row.get("song_path")?;
-                Ok(Song::new(song_id, song_name, song_path))
-            })
+            .query_row([song_id], Self::song_from_row)
             .map_err(|err| match err {
                 rusqliteError::QueryReturnedNoRows => SongBaseError::EntryNotFound,
                 _ => SongBaseError::DatabaseError(err.to_string()),
@@ -119,8 +147,9 @@ impl SongBase {
             Ok(song) => Ok(song),
             Err(err) => {
                 if let SongError::InvalidSongPath = err {
+                    // Kept with its playlist links in case the file comes back
                     connection
-                        .execute("DELETE FROM songs WHERE song_id = ?1", [song_id])
+                        .execute("UPDATE songs SET missing = 1 WHERE song_id = ?1", [song_id])
                         .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
                     Err(SongBaseError::EntryNotFound)
                 } else {
@@ -130,14 +159,73 @@ impl SongBase {
         }
     }
 
-    const RETRIEVE_ID_QUERY: &'static str =
-        "SELECT song_id FROM songs WHERE song_name = ?1 AND song_path = ?2";
+    fn song_from_row(row: &Row) -> Result<Result<Song, SongError>, rusqliteError> {
+        let song_id: u32 = row.get("song_id")?;
+        let song_name: String = row.get("song_name")?;
+        let song_path: String = row.get("song_path")?;
+        let tags = Tags {
+            title: row.get("title")?,
+            artist: row.get("artist")?,
+            album: row.get("album")?,
+            album_artist: row.get("album_artist")?,
+            track_number: row.get("track_number")?,
+            disc_number: row.get("disc_number")?,
+            year: row.get("year")?,
+            genre: row.get("genre")?,
+            duration_ms: row.get("duration_ms")?,
+            rating: row.get("rating")?,
+        };
+        let favorite = row.get("favorite")?;
+        Ok(Song::new(song_id, song_name, song_path).map(|song| Song {
+            favorite,
+            ..song.with_tags(tags)
+        }))
+    }
+
+    /// Inserts the song with its tags, or refreshes the tags when the same
+    /// file is already in the library. Returns true for a new song.
+    fn upsert_song(
+        conn: &Connection,
+        song_name: &str,
+        song_path: &str,
+        tags: &Tags,
+        stamp: Option<FileStamp>,
+    ) -> Result<bool, rusqliteError> {
+        let params = params![
+            song_name,
+            song_path,
+            tags.title,
+            tags.artist,
+            tags.album,
+            tags.album_artist,
+            tags.track_number,
+            tags.disc_number,
+            tags.year,
+            tags.genre,
+            tags.duration_ms,
+            stamp.map(|stamp| stamp.mtime_ms),
+            stamp.map(|stamp| stamp.size),
+            tags.rating,
+        ];
+        match conn.execute(Self::INSERT_SONG_QUERY, params) {
+            Ok(_) => Ok(true),
+            Err(err) if err.sqlite_error_code() == Some(ErrorCode::ConstraintViolation) => {
+                conn.execute(Self::UPDATE_TAGS_QUERY, params)?;
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    const RETRIEVE_ID_QUERY: &'static str = "SELECT song_id FROM songs WHERE song_path = ?1";
     pub fn create_song(
-        conn: &mut Connection,
+        conn: &Connection,
         song_name: &str,
         song_path: &str,
     ) -> Result<u32, SongBaseError> {
-        match conn.execute(Self::INSERT_SONG_QUERY, (song_name, song_path)) {
+        let tags = Tags::read(Path::new(song_path));
+        let stamp = FileStamp::of(Path::new(song_path));
+        match Self::upsert_song(conn, song_name, song_path, &tags, stamp) {
             Err(err) if err.sqlite_error_code() != Some(ErrorCode::ConstraintViolation) => {
                 Err(SongBaseError::DatabaseError(err.to_string()))
             }
@@ -146,47 +234,124 @@ impl SongBase {
                     .prepare(Self::RETRIEVE_ID_QUERY)
                     .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
                 let mut query_result = query_statement
-                    .query([song_name, song_path])
+                    .query([song_path])
                     .map_err(SongBaseError::from)?;
 
-                let song_row = query_result.next().map_err(SongBaseError::from)?.unwrap();
+                let song_row = query_result
+                    .next()
+                    .map_err(SongBaseError::from)?
+                    .ok_or(SongBaseError::EntryNotFound)?;
 
                 Ok(song_row.get("song_id").unwrap())
             }
         }
     }
 
-    pub fn filter_song(&self, song_name: &str) -> Result<Vec<(String, u32)>, SongBaseError> {
+    /// Title matches count the most, then artist, album and finally the path.
+    const FTS_RANK_SQL: &'static str = "bm25(songs_fts, 10.0, 5.0, 3.0, 1.0)";
+
+    /// Turns free text into an FTS5 query where every word must match as a
+    /// prefix, e.g. `bohem rhap` -> `"bohem"* "rhap"*`. `None` when there is
+    /// nothing to search for.
+    fn fts_query(term: &str) -> Option<String> {
+        let words: Vec<String> = term
+            .split(|ch: char| !ch.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| format!("\"{}\"*", word))
+            .collect();
+        (!words.is_empty()).then(|| words.join(" "))
+    }
+
+    /// Songs matching the term, best match first. An empty term lists
+    /// everything, and a term with no match at all is retried fuzzily.
+    pub fn filter_song(&self, song_name: &str) -> Result<Vec<SearchHit>, SongBaseError> {
         let connection = self.conn.lock().unwrap();
 
-        let search_query = "SELECT song_name, song_id FROM songs 
-            WHERE song_name LIKE ?1";
-        let pattern = format!("%{}%", song_name);
+        let (search_query, match_query) = match Self::fts_query(song_name) {
+            Some(match_query) => (
+                format!(
+                    "SELECT {} AS display_name, song_id, rating, favorite
+                    FROM songs_fts JOIN songs ON songs.song_id = songs_fts.rowid
+                    WHERE songs_fts MATCH ?1 AND {} ORDER BY {}",
+                    Self::DISPLAY_NAME_SQL,
+                    Self::LISTED_SQL,
+                    Self::FTS_RANK_SQL
+                ),
+                Some(match_query),
+            ),
+            None => (
+                format!(
+                    "SELECT {0} AS display_name, song_id, rating, favorite FROM songs
+                    WHERE ?1 IS NULL AND {1} ORDER BY {0} COLLATE NOCASE",
+                    Self::DISPLAY_NAME_SQL,
+                    Self::LISTED_SQL
+                ),
+                None,
+            ),
+        };
 
         let mut prepared_statement = connection
-            .prepare(search_query)
+            .prepare(&search_query)
             .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
 
-        let query_result = prepared_statement
-            .query_map([pattern], |row| {
-                Ok((row.get("song_name")?, row.get("song_id")?))
+        let songs: Vec<SearchHit> = prepared_statement
+            .query_map([&match_query], |row| {
+                Ok(SearchHit {
+                    song_id: row.get("song_id")?,
+                    display_name: row.get("display_name")?,
+                    fuzzy_score: None,
+                    detail: None,
+                    rating: row.get("rating")?,
+                    favorite: row.get("favorite")?,
+                })
             })
-            .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
+            .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?
+            .filter_map(|song| song.ok())
+            .collect();
 
-        let mut songs = Vec::new();
-        for song in query_result {
-            if let Ok(song) = song {
-                songs.push(song);
-            } else {
-                continue;
-            }
+        if songs.is_empty() && match_query.is_some() {
+            return Self::fuzzy_search(&connection, song_name);
         }
         Ok(songs)
     }
 
+    /// Lowest `fuzzy_score` that still counts as a match.
+    const FUZZY_THRESHOLD: u8 = 60;
+
+    fn fuzzy_search(conn: &Connection, term: &str) -> Result<Vec<SearchHit>, SongBaseError> {
+        let mut fuzzy_query = conn.prepare(&format!(
+            "SELECT * FROM (
+                SELECT song_id, {} AS display_name, rating, favorite, fuzzy_score(?1,
+                    COALESCE(title, '') || ' ' || COALESCE(artist, '') || ' '
+                    || COALESCE(album, '') || ' ' || song_name) AS score
+                FROM songs WHERE {}
+            ) WHERE score >= ?2 ORDER BY score DESC, display_name LIMIT 50",
+            Self::DISPLAY_NAME_SQL,
+            Self::LISTED_SQL
+        ))?;
+
+        let songs = fuzzy_query
+            .query_map(params![term, Self::FUZZY_THRESHOLD], |row| {
+                Ok(SearchHit {
+                    song_id: row.get("song_id")?,
+                    display_name: row.get("display_name")?,
+                    fuzzy_score: Some(row.get("score")?),
+                    detail: None,
+                    rating: row.get("rating")?,
+                    favorite: row.get("favorite")?,
+                })
+            })?
+            .filter_map(|song| song.ok())
+            .collect();
+        Ok(songs)
+    }
+
     pub fn create_playlist(&self, playlist_name: String) -> Result<u8, SongBaseError> {
-        let connection = self.conn.lock().unwrap();
-        let mut playlist_create_query = connection
+        Self::insert_playlist(&self.conn.lock().unwrap(), &playlist_name)
+    }
+
+    fn insert_playlist(conn: &Connection, playlist_name: &str) -> Result<u8, SongBaseError> {
+        let mut playlist_create_query = conn
             .prepare("INSERT INTO playlists (playlist_name) VALUES (?1) RETURNING playlist_id")
             .map_err(SongBaseError::from)?;
 
@@ -201,209 +366,263 @@ impl SongBase {
             })
     }
 
+    /// A playlist filled from `rule`, a search like
+    /// `rating:>=4 -played:<30 sort:random limit:100`, each time it's loaded.
+    pub fn create_smart_playlist(
+        &self,
+        playlist_name: String,
+        rule: String,
+    ) -> Result<u8, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        Self::check_rule(&connection, &rule)?;
+
+        connection
+            .query_row(
+                "INSERT INTO playlists (playlist_name, playlist_type, playlist_rule)
+                VALUES (?1, 'smart', ?2) RETURNING playlist_id",
+                [playlist_name, rule],
+                |row| row.get("playlist_id"),
+            )
+            .map_err(|err| {
+                if err.sqlite_error_code() == Some(ErrorCode::ConstraintViolation) {
+                    SongBaseError::NameAlreadyExist
+                } else {
+                    SongBaseError::DatabaseError(err.to_string())
+                }
+            })
+    }
+
     pub fn create_playlist_from_path(
         &self,
         playlist_name: String,
         folder_name: PathBuf,
     ) -> Result<(), SongBaseError> {
         let playlist_id = self.create_playlist(playlist_name)?;
-        let mut conn = self.conn.lock().unwrap();
-        let conn = conn.deref_mut();
-        let song_ids: Vec<u32> = folder_name
-            .read_dir()
-            .map_err(|_| SongBaseError::AccessFailed)?
-            .filter(|x| x.is_ok() && Song::is_valid_song_path(&x.as_ref().unwrap().path()))
-            .map(|song| {
-                let song = song.unwrap();
-                self.sender
-                    .send(PlayerAction::ConnectionMessage(format!(
-                        "Added: {:?}",
-                        song.file_name()
-                    )))
-                    .unwrap();
-                Self::create_song(
-                    conn,
-                    song.file_name().to_string_lossy().deref(),
-                    song.path().to_string_lossy().deref(),
-                )
-                .unwrap()
-            })
-            .collect();
+        let song_ids: Vec<u32> = {
+            let mut conn = self.conn.lock().unwrap();
+            let conn = conn.deref_mut();
+            folder_name
+                .read_dir()
+                .map_err(|_| SongBaseError::AccessFailed)?
+                .filter(|x| x.is_ok() && Song::is_valid_song_path(&x.as_ref().unwrap().path()))
+                .filter_map(|song| {
+                    let song = song.unwrap();
+                    self.sender
+                        .send(PlayerAction::ConnectionMessage(format!(
+                            "Added: {:?}",
+                            song.file_name()
+                        )))
+                        .unwrap();
+                    Self::create_song(
+                        conn,
+                        song.file_name().to_string_lossy().deref(),
+                        song.path().to_string_lossy().deref(),
+                    )
+                    .ok()
+                })
+                .collect()
+        };
         self.add_playlist_song(playlist_id, song_ids)?;
         Ok(())
     }
 
-    pub fn get_playlists(&self) -> Result<Vec<(u8, String)>, SongBaseError> {
+    /// Every playlist as `(id, name, rule)`, the rule only for smart ones.
+    pub fn get_playlists(&self) -> Result<Vec<(u8, String, Option<String>)>, SongBaseError> {
         let conn = self.conn.lock().unwrap();
 
         let mut playlist_name_query = conn
-            .prepare("SELECT playlist_id, playlist_name FROM playlists")
+            .prepare("SELECT playlist_id, playlist_name, playlist_rule FROM playlists")
             .map_err(SongBaseError::from)?;
 
         let query_result = playlist_name_query
             .query_map([], |row| {
                 let playlist_id: u8 = row.get("playlist_id")?;
                 let playlist_name: String = row.get("playlist_name")?;
+                let playlist_rule: Option<String> = row.get("playlist_rule")?;
 
-                Ok((playlist_id, playlist_name))
+                Ok((playlist_id, playlist_name, playlist_rule))
             })
             .map_err(SongBaseError::from)?;
 
         Ok(query_result.filter_map(|row| row.ok()).collect())
     }
 
+    /// The playlist's songs. A smart playlist runs its rule now, so it
+    /// follows the library as it changes.
     pub fn get_playlist(&self, playlist_id: u8) -> Result<Playlist, SongBaseError> {
         let connection = self.conn.lock().unwrap();
 
-        let playlist_name_query = "SELECT playlist_name FROM playlists
+        let playlist_name_query = "SELECT playlist_name, playlist_rule FROM playlists
         WHERE playlist_id = ?1";
 
-        let playlist_name: String = connection
+        let (playlist_name, playlist_rule): (String, Option<String>) = connection
             .query_row(playlist_name_query, [playlist_id], |row| {
-                row.get("playlist_name")
+                Ok((row.get("playlist_name")?, row.get("playlist_rule")?))
             })
-            .map_err(|err| {
-                if err.sqlite_error_code() == Some(ErrorCode::NotFound) {
-                    SongBaseError::EntryNotFound
-                } else {
-                    SongBaseError::DatabaseError(err.to_string())
-                }
+            .map_err(|err| match err {
+                rusqliteError::QueryReturnedNoRows => SongBaseError::EntryNotFound,
+                _ => SongBaseError::DatabaseError(err.to_string()),
             })?;
 
-        let mut playlist_songs_query = connection
-            .prepare(
-                "SELECT song_id FROM playlist_song_link
-            WHERE playlist_id=?1",
-            )
-            .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?;
-
-        let mut playlist = Playlist::new(playlist_name.clone());
-
-        let song_ids = playlist_songs_query
-            .query_map([playlist_id], |row| row.get("song_id"))
-            .map_err(|err| SongBaseError::DatabaseError(err.to_string()))?
-            .flatten();
+        if let Some(rule) = playlist_rule {
+            let mut playlist = Playlist::new(&playlist_name);
+            playlist.songs = Self::rule_songs(&connection, &rule)?;
+            playlist.rule = Some(rule);
+            drop(connection);
 
-        for song_id in song_ids {
-            if let Ok(song) = self.find_song_by_id(song_id) {
-                playlist.add_song(song);
-            }
+            self.sender
+                .send(PlayerAction::ConnectionMessage(playlist_name))
+                .unwrap();
+            return Ok(playlist);
         }
 
+        let mut playlist = Playlist::new(playlist_name.clone());
+        playlist.songs = Self::playlist_songs(&connection, playlist_id)?;
+        drop(connection);
+
         self.sender
             .send(PlayerAction::ConnectionMessage(playlist_name))
             .unwrap();
         Ok(playlist)
     }
 
-    pub fn add_playlist_song(
-        &self,
-        playlist_id: u8,
-        song_ids: Vec<u32>,
-    ) -> Result<(), SongBaseError> {
+    pub fn get_artists(&self) -> Result<Vec<ArtistSummary>, SongBaseError> {
         let connection = self.conn.lock().unwrap();
 
-        let playlist_song_add_query = "
-            INSERT INTO playlist_song_relation (song_id, playlist_id) VALUES
-            (?1, ?2)";
+        let mut artist_query = connection.prepare(&format!(
+            "SELECT artist, COUNT(DISTINCT album_id) AS albums, COUNT(*) AS tracks,
+                COALESCE(SUM(duration_ms), 0) AS duration_ms
+            FROM songs WHERE artist IS NOT NULL AND {}
+            GROUP BY artist ORDER BY artist COLLATE NOCASE",
+            Self::LISTED_SQL
+        ))?;
 
-        for id in song_ids {
-            if let Err(err) = connection.execute(playlist_song_add_query, [id, playlist_id as u32])
-            {
-                if err.sqlite_error_code() == Some(ErrorCode::ConstraintViolation) {
-                    continue;
-                } else {
-                    return Err(SongBaseError::DatabaseError(err.to_string()));
-                }
-            }
-        }
+        let artists = artist_query
+            .query_map([], |row| {
+                Ok(ArtistSummary {
+                    artist: row.get("artist")?,
+                    albums: row.get("albums")?,
+                    tracks: row.get("tracks")?,
+                    duration_ms: row.get("duration_ms")?,
+                })
+            })?
+            .filter_map(|artist| artist.ok())
+            .collect();
+        Ok(artists)
+    }
 
-        Ok(())
+    /// Every album, or the ones whose artist matches `artist`.
+    pub fn get_albums(&self, artist: Option<&str>) -> Result<Vec<AlbumSummary>, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+
+        let mut album_query = connection.prepare(&format!(
+            "SELECT albums.album_id, album_name, albums.album_artist, MIN(year) AS year,
+                COUNT(*) AS tracks, COALESCE(SUM(duration_ms), 0) AS duration_ms
+            FROM albums JOIN songs ON songs.album_id = albums.album_id AND {}
+            WHERE ?1 IS NULL OR albums.album_artist LIKE ?1 OR artist LIKE ?1
+            GROUP BY albums.album_id
+            ORDER BY albums.album_artist COLLATE NOCASE, year, album_name COLLATE NOCASE",
+            Self::LISTED_SQL
+        ))?;
+
+        let pattern = artist.map(|artist| format!("%{}%", artist));
+        let albums = album_query
+            .query_map([pattern], |row| {
+                Ok(AlbumSummary {
+                    album_id: row.get("album_id")?,
+                    album_name: row.get("album_name")?,
+                    album_artist: row.get("album_artist")?,
+                    year: row.get("year")?,
+                    tracks: row.get("tracks")?,
+                    duration_ms: row.get("duration_ms")?,
+                })
+            })?
+            .filter_map(|album| album.ok())
+            .collect();
+        Ok(albums)
     }
 
-    pub fn scan_songs(&self, path: Option<String>) -> Result<String, SongBaseError> {
-        let path = match path {
-            Some(p) => PathBuf::from(p),
-            None => match dirs::audio_dir() {
-                Some(p) => p,
-                None => return Err(SongBaseError::InvalidPath),
-            },
-        };
+    /// The album's songs in disc and track order, as a playlist named after it.
+    pub fn get_album(&self, album_id: u32) -> Result<Playlist, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
 
-        if !path.exists() {
-            Err(SongBaseError::InvalidPath)
-        } else {
-            let connection = Arc::clone(&self.conn);
-            let sender_clone = self.sender.clone();
-            let path_clone = path.clone();
+        let album_name: String = connection
+            .query_row(
+                "SELECT album_name FROM albums WHERE album_id = ?1",
+                [album_id],
+                |row| row.get("album_name"),
+            )
+            .map_err(|err| match err {
+                rusqliteError::QueryReturnedNoRows => SongBaseError::EntryNotFound,
+                _ => SongBaseError::DatabaseError(err.to_string()),
+            })?;
 
-            thread::spawn(move || Self::fetch_songs(path_clone, &connection, &sender_clone));
-            Ok(format!("{}", path.to_string_lossy()))
-        }
+        let mut album_songs_query = connection.prepare(&format!(
+            "SELECT * FROM songs WHERE album_id = ?1 AND {}
+            ORDER BY COALESCE(disc_number, 1), track_number, song_name",
+            Self::LISTED_SQL
+        ))?;
+
+        let mut album = Playlist::new(album_name);
+        album_songs_query
+            .query_map([album_id], Self::song_from_row)?
+            .flatten()
+            .flatten()
+            .for_each(|song| album.add_song(song));
+        Ok(album)
     }
 
-    fn fetch_songs(path: PathBuf, conn: &Arc<Mutex<Connection>>, sender: &Sender<PlayerAction>) {
-        let read_dir = path.read_dir();
-        if read_dir.is_err() {
-            let message = format!("Can't read dir: {}", path.to_str().unwrap());
-            sender
-                .send(PlayerAction::ConnectionMessage(message))
-                .unwrap();
-            return;
+    /// Fails unless the playlist exists and its songs are picked by hand.
+    fn check_manual(conn: &Connection, playlist_id: u8) -> Result<(), SongBaseError> {
+        let playlist_type: String = conn
+            .query_row(
+                "SELECT playlist_type FROM playlists WHERE playlist_id = ?1",
+                [playlist_id],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(SongBaseError::EntryNotFound)?;
+        match playlist_type.as_str() {
+            "smart" => Err(SongBaseError::SmartPlaylist),
+            _ => Ok(()),
         }
+    }
 
-        for entry in read_dir.unwrap() {
-            if entry.is_err() {
-                continue;
-            }
-            let entry = entry.unwrap();
-            let entry_path = entry.path();
-
-            if entry_path.is_dir() {
-                let dir_name = entry_path.file_name();
-                match dir_name {
-                    Some(dir_name) if dir_name != "node_modules" || dir_name != "target" => {
-                        let connection = Arc::clone(conn);
-                        Self::fetch_songs(entry_path, &connection, sender);
-                    }
-                    _ => continue,
-                }
-                continue;
-            }
+    /// Links the songs to the playlist, returning how many were new to it.
+    pub fn add_playlist_song(
+        &self,
+        playlist_id: u8,
+        song_ids: Vec<u32>,
+    ) -> Result<usize, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        Self::check_manual(&connection, playlist_id)?;
+        Self::link_songs(&connection, playlist_id, song_ids)
+    }
 
-            let file_extension = entry_path.extension();
-            match file_extension {
-                Some(ext) if matches!(ext.to_str().unwrap(), "ogg" | "wav" | "mp3") => {
-                    let file_name = entry_path.file_name();
-                    if file_name.is_none() {
-                        continue;
-                    }
-                    let file_name = file_name.unwrap().to_str().unwrap();
-
-                    let execute_query = conn.lock().unwrap().execute(
-                        Self::INSERT_SONG_QUERY,
-                        (file_name, entry_path.to_str().unwrap()),
-                    );
-                    if let Err(err) = execute_query {
-                        if let Some(ErrorCode::ConstraintViolation) = err.sqlite_error_code() {
-                            continue;
-                        } else {
-                            let message = format!("Database error: {}", err);
-                            sender
-                                .send(PlayerAction::ConnectionMessage(message))
-                                .map_err(|_| {})
-                                .unwrap();
-                        }
-                    } else {
-                        let message = format!("Added {}", file_name);
-                        sender
-                            .send(PlayerAction::ConnectionMessage(message))
-                            .map_err(|_| {})
-                            .unwrap();
-                    }
+    /// Appends the songs to the playlist, skipping the ones already in it.
+    /// Returns how many were added.
+    fn link_songs(
+        conn: &Connection,
+        playlist_id: u8,
+        song_ids: Vec<u32>,
+    ) -> Result<usize, SongBaseError> {
+        // New songs go to the end
+        let playlist_song_add_query = "
+            INSERT INTO playlist_song_link (song_id, playlist_id, position) VALUES
+            (?1, ?2, (SELECT COALESCE(MAX(position), 0) + 1 FROM playlist_song_link
+                WHERE playlist_id = ?2))";
+
+        let mut added = 0;
+        for id in song_ids {
+            match conn.execute(playlist_song_add_query, [id, playlist_id as u32]) {
+                Ok(_) => added += 1,
+                Err(err) if err.sqlite_error_code() == Some(ErrorCode::ConstraintViolation) => {
+                    continue
                 }
-                _ => continue,
+                Err(err) => return Err(SongBaseError::DatabaseError(err.to_string())),
             }
         }
+
+        Ok(added)
     }
 }
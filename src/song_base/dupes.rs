@@ -0,0 +1,388 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    fmt::Display,
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use super::{scan::worker_count, SongBase};
+use crate::{
+    error::SongBaseError,
+    fingerprint::{self, Fingerprint},
+    player::PlayerAction,
+};
+
+/// Lowest `Fingerprint::similarity` of two copies of the same recording.
+const SAME_AUDIO: f32 = 0.7;
+/// Copies of a recording can't differ in length by more than this.
+const DURATION_SLACK_MS: u64 = 2000;
+/// Hashes and fingerprints written in one transaction.
+const FINGERPRINT_BATCH_SIZE: usize = 64;
+/// Formats that beat any bitrate when picking the copy to keep.
+const LOSSLESS: &[&str] = &["flac", "wav", "aiff", "aif"];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DupeKind {
+    /// Byte for byte the same file.
+    SameFile,
+    /// The same recording, possibly in another format or bitrate.
+    SameAudio,
+}
+
+impl Display for DupeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SameFile => write!(f, "Identical Files"),
+            Self::SameAudio => write!(f, "Same Recording"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DupeSong {
+    pub song_id: u32,
+    pub display_name: String,
+    pub song_path: String,
+    pub duration_ms: Option<u64>,
+    pub file_size: Option<u64>,
+}
+
+impl DupeSong {
+    /// The extension, upper cased.
+    pub fn format(&self) -> String {
+        Path::new(&self.song_path)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_uppercase())
+            .unwrap_or_default()
+    }
+
+    /// Average over the whole file, tags and artwork included.
+    pub fn bitrate_kbps(&self) -> Option<u64> {
+        match (self.file_size, self.duration_ms) {
+            (Some(size), Some(duration)) if duration > 0 => Some(size * 8 / duration),
+            _ => None,
+        }
+    }
+
+    fn is_lossless(&self) -> bool {
+        LOSSLESS.contains(&self.format().to_lowercase().as_str())
+    }
+}
+
+/// Copies of one song, the one worth keeping first.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DupeGroup {
+    pub kind: DupeKind,
+    pub songs: Vec<DupeSong>,
+}
+
+/// A song considered for duplicates, with what's needed to compare it.
+struct Candidate {
+    song: DupeSong,
+    content_hash: String,
+}
+
+impl SongBase {
+    /// Looks for songs stored more than once, in the background: files
+    /// that weren't hashed and fingerprinted yet are first, then copies are
+    /// grouped. The groups arrive as `PlayerAction::DupesFound`.
+    pub fn find_dupes(&self) {
+        let connection = Arc::clone(&self.conn);
+        let sender = self.sender.clone();
+
+        thread::spawn(move || {
+            let result = Self::fingerprint_songs(&connection, &sender)
+                .and_then(|_| Self::group_dupes(&connection));
+            let _ = sender.send(PlayerAction::DupesFound(result));
+        });
+    }
+
+    /// Keeps `keep` and hides `duplicates` behind it, moving their playlist
+    /// links, labels, plays, rating and favorite over. The files stay where
+    /// they are. Returns how many playlist links were moved.
+    pub fn merge_dupes(&self, keep: u32, duplicates: &[u32]) -> Result<usize, SongBaseError> {
+        let mut connection = self.conn.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        let mut moved = 0;
+        for &duplicate in duplicates.iter().filter(|&&song_id| song_id != keep) {
+            moved += Self::move_song_history(&tx, keep, duplicate)?;
+            tx.execute(
+                "UPDATE songs SET merged_into = ?1 WHERE song_id = ?2 OR merged_into = ?2",
+                [keep, duplicate],
+            )?;
+        }
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    /// Moves the playlist links, labels and plays of `from` to `keep`, which
+    /// also inherits its counts, rating and favorite. Returns how many
+    /// playlist links were moved.
+    fn move_song_history(conn: &Connection, keep: u32, from: u32) -> rusqlite::Result<usize> {
+        conn.execute(
+            "INSERT OR IGNORE INTO playlist_song_link (playlist_id, song_id, position)
+            SELECT playlist_id, ?1, position FROM playlist_song_link WHERE song_id = ?2",
+            [keep, from],
+        )?;
+        let moved = conn.execute("DELETE FROM playlist_song_link WHERE song_id = ?1", [from])?;
+        conn.execute(
+            "INSERT OR IGNORE INTO song_labels (label_id, song_id)
+            SELECT label_id, ?1 FROM song_labels WHERE song_id = ?2",
+            [keep, from],
+        )?;
+        conn.execute("DELETE FROM song_labels WHERE song_id = ?1", [from])?;
+
+        conn.execute(
+            "UPDATE plays SET song_id = ?1 WHERE song_id = ?2",
+            [keep, from],
+        )?;
+        conn.execute(
+            "UPDATE songs SET
+                play_count = play_count + (SELECT play_count FROM songs WHERE song_id = ?2),
+                skip_count = skip_count + (SELECT skip_count FROM songs WHERE song_id = ?2),
+                last_played = (SELECT MAX(last_played) FROM songs WHERE song_id IN (?1, ?2)),
+                rating = COALESCE(rating, (SELECT rating FROM songs WHERE song_id = ?2)),
+                rating_set = rating_set OR (SELECT rating_set FROM songs WHERE song_id = ?2),
+                favorite = favorite OR (SELECT favorite FROM songs WHERE song_id = ?2)
+            WHERE song_id = ?1",
+            [keep, from],
+        )?;
+        conn.execute(
+            "UPDATE songs SET play_count = 0, skip_count = 0 WHERE song_id = ?1",
+            [from],
+        )?;
+        Ok(moved)
+    }
+
+    /// Hashes and fingerprints the listed songs that don't have them yet,
+    /// on a pool of threads, writing in small batches.
+    fn fingerprint_songs(
+        conn: &Arc<Mutex<Connection>>,
+        sender: &mpsc::Sender<PlayerAction>,
+    ) -> Result<(), SongBaseError> {
+        let pending: Vec<(u32, String)> = {
+            let connection = conn.lock().unwrap();
+            let mut pending_query = connection.prepare(&format!(
+                "SELECT song_id, song_path FROM songs
+                WHERE {} AND (content_hash IS NULL OR fingerprint IS NULL)",
+                Self::LISTED_SQL
+            ))?;
+            let pending = pending_query
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .filter_map(|song| song.ok())
+                .collect();
+            pending
+        };
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let message = format!(
+            "Fingerprinting {} Songs, This May Take a While",
+            pending.len()
+        );
+        let _ = sender.send(PlayerAction::ConnectionMessage(message));
+
+        let pending = Mutex::new(pending.into_iter());
+        let (result_sender, results) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..worker_count() {
+                let result_sender = result_sender.clone();
+                let pending = &pending;
+                scope.spawn(move || loop {
+                    let Some((song_id, song_path)) = pending.lock().unwrap().next() else {
+                        break;
+                    };
+                    let path = Path::new(&song_path);
+                    // Unreadable files are left for a later run
+                    let Ok(content_hash) = fingerprint::content_hash(path) else {
+                        continue;
+                    };
+                    // Undecodable ones get an empty fingerprint, so they aren't retried
+                    let fingerprint = Fingerprint::compute(path).unwrap_or_default();
+                    if result_sender
+                        .send((song_id, content_hash, fingerprint.to_bytes()))
+                        .is_err()
+                    {
+                        break;
+                    }
+                });
+            }
+            drop(result_sender);
+
+            let mut batch = Vec::with_capacity(FINGERPRINT_BATCH_SIZE);
+            let mut results = results.into_iter().peekable();
+            while results.peek().is_some() {
+                batch.extend(results.by_ref().take(FINGERPRINT_BATCH_SIZE));
+
+                let mut connection = conn.lock().unwrap();
+                let tx = connection.transaction()?;
+                for (song_id, content_hash, fingerprint) in batch.drain(..) {
+                    tx.execute(
+                        "UPDATE songs SET content_hash = ?2, fingerprint = ?3 WHERE song_id = ?1",
+                        params![song_id, content_hash, fingerprint],
+                    )?;
+                }
+                tx.commit()?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Groups listed songs sharing a content hash, or sounding the same.
+    /// Fingerprints are only compared between songs of about the same
+    /// length, and loaded as they're needed.
+    fn group_dupes(conn: &Arc<Mutex<Connection>>) -> Result<Vec<DupeGroup>, SongBaseError> {
+        let mut candidates: Vec<Candidate> = {
+            let connection = conn.lock().unwrap();
+            let mut candidate_query = connection.prepare(&format!(
+                "SELECT song_id, {} AS display_name, song_path, duration_ms, file_size,
+                    content_hash
+                FROM songs WHERE {} AND content_hash IS NOT NULL",
+                Self::DISPLAY_NAME_SQL,
+                Self::LISTED_SQL
+            ))?;
+            let candidates = candidate_query
+                .query_map([], |row| {
+                    Ok(Candidate {
+                        song: DupeSong {
+                            song_id: row.get("song_id")?,
+                            display_name: row.get("display_name")?,
+                            song_path: row.get("song_path")?,
+                            duration_ms: row.get("duration_ms")?,
+                            file_size: row.get("file_size")?,
+                        },
+                        content_hash: row.get("content_hash")?,
+                    })
+                })?
+                .filter_map(|candidate| candidate.ok())
+                .collect();
+            candidates
+        };
+        // Untimed songs sort first and are only matched by hash
+        candidates.sort_by_key(|candidate| candidate.song.duration_ms);
+
+        let mut groups = UnionFind::new(candidates.len());
+        let mut by_hash: HashMap<&str, usize> = HashMap::new();
+        for (index, candidate) in candidates.iter().enumerate() {
+            match by_hash.get(candidate.content_hash.as_str()) {
+                Some(&first) => groups.union(first, index),
+                None => {
+                    by_hash.insert(&candidate.content_hash, index);
+                }
+            }
+        }
+
+        let mut fingerprints: HashMap<usize, Fingerprint> = HashMap::new();
+        for index in 0..candidates.len() {
+            let Some(duration) = candidates[index].song.duration_ms else {
+                continue;
+            };
+            fingerprints.retain(|&loaded, _| loaded >= index);
+
+            for other in index + 1..candidates.len() {
+                let Some(other_duration) = candidates[other].song.duration_ms else {
+                    continue;
+                };
+                if other_duration > duration + DURATION_SLACK_MS {
+                    break;
+                }
+                if groups.find(index) == groups.find(other) {
+                    continue;
+                }
+
+                for loading in [index, other] {
+                    if let Entry::Vacant(entry) = fingerprints.entry(loading) {
+                        entry.insert(load_fingerprint(conn, candidates[loading].song.song_id)?);
+                    }
+                }
+                let (a, b) = (&fingerprints[&index], &fingerprints[&other]);
+                if !a.is_empty() && !b.is_empty() && a.similarity(b) >= SAME_AUDIO {
+                    groups.union(index, other);
+                }
+            }
+        }
+
+        let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+        for index in 0..candidates.len() {
+            members.entry(groups.find(index)).or_default().push(index);
+        }
+
+        let mut dupe_groups: Vec<DupeGroup> = members
+            .into_values()
+            .filter(|indexes| indexes.len() > 1)
+            .map(|indexes| {
+                let first_hash = &candidates[indexes[0]].content_hash;
+                let kind = match indexes
+                    .iter()
+                    .all(|&index| &candidates[index].content_hash == first_hash)
+                {
+                    true => DupeKind::SameFile,
+                    false => DupeKind::SameAudio,
+                };
+
+                let mut songs: Vec<DupeSong> = indexes
+                    .iter()
+                    .map(|&index| candidates[index].song.clone())
+                    .collect();
+                songs.sort_by_key(|song| {
+                    (
+                        !song.is_lossless(),
+                        std::cmp::Reverse(song.bitrate_kbps()),
+                        song.song_id,
+                    )
+                });
+                DupeGroup { kind, songs }
+            })
+            .collect();
+        dupe_groups.sort_by_key(|group| group.songs[0].display_name.to_lowercase());
+        Ok(dupe_groups)
+    }
+}
+
+fn load_fingerprint(
+    conn: &Arc<Mutex<Connection>>,
+    song_id: u32,
+) -> Result<Fingerprint, SongBaseError> {
+    let bytes: Option<Vec<u8>> = conn
+        .lock()
+        .unwrap()
+        .prepare_cached("SELECT fingerprint FROM songs WHERE song_id = ?1")?
+        .query_row([song_id], |row| row.get(0))
+        .optional()?
+        .flatten();
+    Ok(bytes
+        .map(|bytes| Fingerprint::from_bytes(&bytes))
+        .unwrap_or_default())
+}
+
+/// Disjoint sets over `0..len`, joined by `union`.
+struct UnionFind(Vec<usize>);
+
+impl UnionFind {
+    fn new(len: usize) -> Self {
+        Self((0..len).collect())
+    }
+
+    fn find(&mut self, index: usize) -> usize {
+        let mut root = index;
+        while self.0[root] != root {
+            root = self.0[root];
+        }
+
+        let mut current = index;
+        while self.0[current] != root {
+            (current, self.0[current]) = (self.0[current], root);
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.0[b] = a;
+    }
+}
@@ -0,0 +1,323 @@
+use rusqlite::{params_from_iter, types::Value, Connection, OptionalExtension};
+
+use super::SongBase;
+use crate::{
+    error::SongBaseError,
+    query::{Query, QueryError, Term, TermKind},
+    song::{SearchHit, Song},
+};
+
+/// How deep saved filters may pull in other saved filters.
+const MAX_FILTER_DEPTH: usize = 8;
+
+impl SongBase {
+    /// Runs what was typed into the search pane. Plain words take the ranked
+    /// search with its fuzzy fallback, anything using the query grammar is
+    /// turned into SQL with every value bound as a parameter.
+    pub fn search(&self, text: &str) -> Result<Vec<SearchHit>, SongBaseError> {
+        let query = Query::parse(text)?;
+        if query.is_plain() {
+            return self.filter_song(&query.words());
+        }
+
+        let connection = self.conn.lock().unwrap();
+        let detail = query
+            .sorts()
+            .first()
+            .and_then(|(field, _)| field.detail_sql())
+            .unwrap_or("NULL");
+        let columns = format!(
+            "{} AS display_name, song_id, {} AS detail, rating, favorite",
+            Self::DISPLAY_NAME_SQL,
+            detail
+        );
+        let (search_query, values) = select_songs(&connection, &query, &columns)?;
+
+        let mut search_statement = connection.prepare(&search_query)?;
+        let songs = search_statement
+            .query_map(params_from_iter(values.iter()), |row| {
+                Ok(SearchHit {
+                    song_id: row.get("song_id")?,
+                    display_name: row.get("display_name")?,
+                    fuzzy_score: None,
+                    detail: row.get("detail")?,
+                    rating: row.get("rating")?,
+                    favorite: row.get("favorite")?,
+                })
+            })?
+            .filter_map(|song| song.ok())
+            .collect();
+        Ok(songs)
+    }
+
+    /// The songs a smart playlist's `rule` picks right now, in its order.
+    pub(super) fn rule_songs(conn: &Connection, rule: &str) -> Result<Vec<Song>, SongBaseError> {
+        let (rule_query, values) = select_songs(conn, &Query::parse(rule)?, "songs.*")?;
+        let mut rule_statement = conn.prepare(&rule_query)?;
+        let songs = rule_statement
+            .query_map(params_from_iter(values.iter()), Self::song_from_row)?
+            .flatten()
+            .flatten()
+            .collect();
+        Ok(songs)
+    }
+
+    /// Checks that `rule` parses and only uses saved filters that exist.
+    pub(super) fn check_rule(conn: &Connection, rule: &str) -> Result<(), SongBaseError> {
+        select_songs(conn, &Query::parse(rule)?, "songs.song_id").map(|_| ())
+    }
+
+    /// Stores the query under `name` (replacing an older one), usable as
+    /// `@name` in later searches. The query is checked before it's saved.
+    pub fn save_filter(&self, name: &str, query: &str) -> Result<(), SongBaseError> {
+        let name = name.trim_start_matches('@').to_lowercase();
+        if name.is_empty() {
+            return Err(SongBaseError::InvalidQuery(QueryError {
+                position: 0,
+                message: "a filter needs a name".to_string(),
+            }));
+        }
+
+        let connection = self.conn.lock().unwrap();
+        let mut stack = vec![name.clone()];
+        for term in Query::parse(query)?.terms {
+            condition(&connection, &term, &mut Vec::new(), &mut stack)?;
+        }
+
+        connection.execute(
+            "INSERT INTO saved_filters (filter_name, filter_query) VALUES (?1, ?2)
+            ON CONFLICT (filter_name) DO UPDATE SET filter_query = excluded.filter_query",
+            [&name, query],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_filter(&self, name: &str) -> Result<(), SongBaseError> {
+        let name = name.trim_start_matches('@').to_lowercase();
+        let connection = self.conn.lock().unwrap();
+        match connection.execute("DELETE FROM saved_filters WHERE filter_name = ?1", [name])? {
+            0 => Err(SongBaseError::EntryNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    /// Saved filters as `(name, query)`, by name.
+    pub fn get_filters(&self) -> Result<Vec<(String, String)>, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        let mut filter_query = connection
+            .prepare("SELECT filter_name, filter_query FROM saved_filters ORDER BY filter_name")?;
+
+        let filters = filter_query
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .filter_map(|filter| filter.ok())
+            .collect();
+        Ok(filters)
+    }
+}
+
+/// SQL selecting `columns` of the songs `query` matches, in the order it
+/// asks for, with its values in placeholder order.
+fn select_songs(
+    conn: &Connection,
+    query: &Query,
+    columns: &str,
+) -> Result<(String, Vec<Value>), SongBaseError> {
+    let mut values = Vec::new();
+
+    // Words that must match drive the ranking, the rest only filter
+    let match_query = SongBase::fts_query(&query.words());
+    if let Some(match_query) = &match_query {
+        values.push(Value::Text(match_query.clone()));
+    }
+
+    let mut conditions = vec![SongBase::LISTED_SQL.to_string()];
+    for term in query
+        .terms
+        .iter()
+        .filter(|term| term.negated || !matches!(term.kind, TermKind::Word(_)))
+    {
+        conditions.extend(condition(conn, term, &mut values, &mut Vec::new())?);
+    }
+    let conditions = conditions.join(" AND ");
+
+    // Sorts come first, the ranking (or the name) breaks ties
+    let mut order: Vec<String> = query
+        .sorts()
+        .iter()
+        .map(|(field, descending)| match descending {
+            true => format!("{} DESC NULLS LAST", field.column()),
+            false => format!("{} ASC NULLS LAST", field.column()),
+        })
+        .collect();
+    order.push(match match_query {
+        Some(_) => SongBase::FTS_RANK_SQL.to_string(),
+        None => format!("{} COLLATE NOCASE", SongBase::DISPLAY_NAME_SQL),
+    });
+
+    let mut select_query = match match_query {
+        Some(_) => format!(
+            "SELECT {} FROM songs_fts JOIN songs ON songs.song_id = songs_fts.rowid
+            WHERE songs_fts MATCH ? AND {} ORDER BY {}",
+            columns,
+            conditions,
+            order.join(", ")
+        ),
+        None => format!(
+            "SELECT {} FROM songs WHERE {} ORDER BY {}",
+            columns,
+            conditions,
+            order.join(", ")
+        ),
+    };
+    if let Some(limit) = query.limit() {
+        select_query.push_str(" LIMIT ?");
+        values.push(Value::Integer(limit as i64));
+    }
+    Ok((select_query, values))
+}
+
+/// The SQL for one term, pushing its values in placeholder order. `stack`
+/// holds the saved filters being expanded, to catch ones that loop.
+fn condition(
+    conn: &Connection,
+    term: &Term,
+    values: &mut Vec<Value>,
+    stack: &mut Vec<String>,
+) -> Result<Option<String>, SongBaseError> {
+    let sql = match &term.kind {
+        TermKind::Word(word) => SongBase::fts_query(word).map(|match_query| {
+            values.push(Value::Text(match_query));
+            "songs.song_id IN (SELECT rowid FROM songs_fts WHERE songs_fts MATCH ?)".to_string()
+        }),
+        TermKind::Text(field, value) => {
+            values.push(Value::Text(format!("%{}%", escape_like(value))));
+            Some(format!("{} LIKE ? ESCAPE '\\'", field.column()))
+        }
+        TermKind::Number(field, comparison) => {
+            let (sql, numbers) = comparison.to_sql(field.column());
+            values.extend(numbers.into_iter().map(Value::Integer));
+            Some(sql)
+        }
+        TermKind::Saved(name) => saved_condition(conn, term, name, values, stack)?,
+        TermKind::Label(label) => {
+            values.push(Value::Text(label.clone()));
+            Some(
+                "songs.song_id IN (SELECT song_id FROM song_labels
+                    JOIN labels ON labels.label_id = song_labels.label_id
+                    WHERE labels.label_name = ?)"
+                    .to_string(),
+            )
+        }
+        // Only order or cut the results, `select_songs` takes care of them
+        TermKind::Sort(..) | TermKind::Limit(_) => None,
+    };
+
+    // A missing tag never matches, so negating keeps the untagged songs
+    Ok(sql.map(|sql| match term.negated {
+        true => format!("NOT COALESCE(({}), 0)", sql),
+        false => sql,
+    }))
+}
+
+fn saved_condition(
+    conn: &Connection,
+    term: &Term,
+    name: &str,
+    values: &mut Vec<Value>,
+    stack: &mut Vec<String>,
+) -> Result<Option<String>, SongBaseError> {
+    let error = |message: String| {
+        SongBaseError::InvalidQuery(QueryError {
+            position: term.position,
+            message,
+        })
+    };
+
+    if stack.iter().any(|saved| saved == name) || stack.len() >= MAX_FILTER_DEPTH {
+        return Err(error(format!("filter @{} refers back to itself", name)));
+    }
+
+    let saved_query: Option<String> = conn
+        .query_row(
+            "SELECT filter_query FROM saved_filters WHERE filter_name = ?1",
+            [name],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let saved_query = saved_query.ok_or_else(|| error(format!("no saved filter @{}", name)))?;
+    let saved_query =
+        Query::parse(&saved_query).map_err(|err| error(format!("in @{}: {}", name, err)))?;
+
+    stack.push(name.to_string());
+    let mut conditions = Vec::new();
+    for saved_term in &saved_query.terms {
+        match condition(conn, saved_term, values, stack) {
+            Ok(sql) => conditions.extend(sql),
+            Err(SongBaseError::InvalidQuery(err)) => {
+                return Err(error(format!("in @{}: {}", name, err.message)))
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    stack.pop();
+
+    Ok((!conditions.is_empty()).then(|| format!("({})", conditions.join(" AND "))))
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn message(result: Result<impl std::fmt::Debug, SongBaseError>) -> String {
+        match result {
+            Err(SongBaseError::InvalidQuery(err)) => err.message,
+            other => panic!("expected a query error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn saved_filters_that_loop_are_refused() {
+        let (sender, _receiver) = mpsc::channel();
+        let song_base = SongBase::init(SongBase::IN_MEMORY, sender).unwrap();
+        song_base.save_filter("@Old", "year:<1980").unwrap();
+        song_base.save_filter("classics", "@old -live").unwrap();
+        assert!(song_base.search("@classics").unwrap().is_empty());
+
+        assert_eq!(
+            message(song_base.save_filter("loop", "@loop")),
+            "filter @loop refers back to itself"
+        );
+        assert_eq!(
+            message(song_base.save_filter("old", "@classics")),
+            "in @classics: filter @old refers back to itself"
+        );
+        assert_eq!(
+            message(song_base.search("@missing")),
+            "no saved filter @missing"
+        );
+
+        // One slipped into the table some other way is caught when searched
+        song_base
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE saved_filters SET filter_query = '@classics' WHERE filter_name = 'old'",
+                [],
+            )
+            .unwrap();
+        assert_eq!(
+            message(song_base.search("@classics")),
+            "in @classics: in @old: filter @classics refers back to itself"
+        );
+    }
+}
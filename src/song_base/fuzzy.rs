@@ -0,0 +1,127 @@
+use rusqlite::{functions::FunctionFlags, Connection};
+
+/// Registers `fuzzy_score(needle, haystack)` on the connection, returning 0-100.
+pub fn register(conn: &Connection) -> rusqlite::Result<()> {
+    conn.create_scalar_function(
+        "fuzzy_score",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let needle = ctx.get::<Option<String>>(0)?.unwrap_or_default();
+            let haystack = ctx.get::<Option<String>>(1)?.unwrap_or_default();
+            Ok(fuzzy_score(&needle, &haystack))
+        },
+    )
+}
+
+/// How well `needle` matches `haystack` despite typos, from 0 to 100.
+///
+/// Every word of the needle is compared with each word of the haystack (and
+/// with the start of it, so unfinished words still match) by edit distance,
+/// counting swapped letters as one edit. The needle's score is the average of
+/// its words' best similarities.
+pub fn fuzzy_score(needle: &str, haystack: &str) -> u8 {
+    let needle = words(needle);
+    let haystack = words(haystack);
+    if needle.is_empty() || haystack.is_empty() {
+        return 0;
+    }
+
+    let total: f64 = needle
+        .iter()
+        .map(|word| {
+            haystack
+                .iter()
+                .map(|candidate| {
+                    let prefix: Vec<char> = candidate.iter().take(word.len()).copied().collect();
+                    similarity(word, candidate).max(similarity(word, &prefix))
+                })
+                .fold(0.0, f64::max)
+        })
+        .sum();
+
+    (total / needle.len() as f64 * 100.0).round() as u8
+}
+
+fn words(text: &str) -> Vec<Vec<char>> {
+    text.split(|ch: char| !ch.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase().chars().collect())
+        .collect()
+}
+
+fn similarity(a: &[char], b: &[char]) -> f64 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    1.0 - edit_distance(a, b) as f64 / longest as f64
+}
+
+/// Optimal string alignment distance: insertions, deletions, substitutions
+/// and transpositions of neighbouring letters each cost one.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let width = b.len() + 1;
+    let mut distances = vec![0; (a.len() + 1) * width];
+    for i in 0..=a.len() {
+        distances[i * width] = i;
+    }
+    for (j, distance) in distances.iter_mut().take(width).enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[(i - 1) * width + j] + 1)
+                .min(distances[i * width + j - 1] + 1)
+                .min(distances[(i - 1) * width + j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[(i - 2) * width + j - 2] + 1);
+            }
+            distances[i * width + j] = distance;
+        }
+    }
+
+    distances[a.len() * width + b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(a: &str, b: &str) -> usize {
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        edit_distance(&a, &b)
+    }
+
+    #[test]
+    fn swapped_letters_cost_one_edit() {
+        assert_eq!(distance("kitten", "sitting"), 3);
+        assert_eq!(distance("qeuen", "queen"), 1);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("abc", "abc"), 0);
+    }
+
+    #[test]
+    fn scores_forgive_typos_and_unfinished_words() {
+        let song = "Queen - Bohemian Rhapsody";
+        assert_eq!(fuzzy_score("queen", song), 100);
+        assert_eq!(fuzzy_score("BOHEM", song), 100);
+        assert_eq!(fuzzy_score("qeuen", song), 80);
+        assert_eq!(fuzzy_score("queen rapsody", song), 94);
+        assert_eq!(fuzzy_score("xyz", song), 0);
+        assert_eq!(fuzzy_score("", song), 0);
+        assert_eq!(fuzzy_score("queen", " - "), 0);
+    }
+
+    #[test]
+    fn registered_for_sql() {
+        let conn = Connection::open_in_memory().unwrap();
+        register(&conn).unwrap();
+        let score = |sql: &str| conn.query_row(sql, [], |row| row.get::<_, u8>(0)).unwrap();
+        assert_eq!(score("SELECT fuzzy_score('qeuen', 'Queen')"), 80);
+        assert_eq!(score("SELECT fuzzy_score('queen', NULL)"), 0);
+    }
+}
@@ -0,0 +1,393 @@
+use std::{fmt::Display, path::Path};
+
+use rusqlite::Connection;
+
+use super::SongBase;
+use crate::{error::SongBaseError, migrations};
+
+/// Tables and the columns the rest of `SongBase` relies on.
+const EXPECTED_SCHEMA: &[(&str, &[&str])] = &[
+    (
+        "songs",
+        &[
+            "song_id",
+            "song_name",
+            "song_path",
+            "title",
+            "artist",
+            "album",
+            "album_artist",
+            "track_number",
+            "disc_number",
+            "year",
+            "genre",
+            "duration_ms",
+            "album_id",
+            "file_mtime",
+            "file_size",
+            "missing",
+            "content_hash",
+            "fingerprint",
+            "merged_into",
+            "play_count",
+            "skip_count",
+            "last_played",
+            "rating",
+            "favorite",
+            "rating_set",
+        ],
+    ),
+    ("albums", &["album_id", "album_name", "album_artist"]),
+    ("songs_fts", &["title", "artist", "album", "song_path"]),
+    (
+        "playlists",
+        &[
+            "playlist_id",
+            "playlist_name",
+            "playlist_type",
+            "playlist_rule",
+        ],
+    ),
+    (
+        "playlist_song_link",
+        &["playlist_id", "song_id", "position"],
+    ),
+    ("saved_filters", &["filter_name", "filter_query"]),
+    ("library_roots", &["root_id", "root_path"]),
+    ("scan_exclusions", &["pattern"]),
+    ("settings", &["setting_key", "setting_value"]),
+    ("plays", &["play_id", "song_id", "played_at", "listened_ms"]),
+    ("labels", &["label_id", "label_name"]),
+    ("song_labels", &["label_id", "song_id"]),
+];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum IssueKind {
+    Schema,
+    ForeignKey,
+    DanglingLink,
+    MissingFile,
+    DuplicatePath,
+}
+
+impl Display for IssueKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Schema => write!(f, "Schema"),
+            Self::ForeignKey => write!(f, "Foreign Key"),
+            Self::DanglingLink => write!(f, "Dangling Link"),
+            Self::MissingFile => write!(f, "Missing File"),
+            Self::DuplicatePath => write!(f, "Duplicate Path"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub detail: String,
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct IntegrityReport {
+    pub issues: Vec<Issue>,
+    /// Rows changed by `--fix`, `None` when it was only a check.
+    pub repaired: Option<usize>,
+}
+
+impl IntegrityReport {
+    fn push<S: ToString>(&mut self, kind: IssueKind, detail: S) {
+        self.issues.push(Issue {
+            kind,
+            detail: detail.to_string(),
+        })
+    }
+
+    pub fn count(&self, kind: IssueKind) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.kind == kind)
+            .count()
+    }
+
+    pub fn summary(&self) -> String {
+        let found = if self.issues.is_empty() {
+            "Library Check: No Problems Found".to_string()
+        } else {
+            format!("Library Check: {} Problems Found", self.issues.len())
+        };
+        match self.repaired {
+            Some(repaired) => format!("{}, Repaired {} Rows", found, repaired),
+            None => found,
+        }
+    }
+}
+
+impl SongBase {
+    /// Validates the schema, foreign keys, playlist links and the files
+    /// behind each song. With `repair`, everything but schema problems is
+    /// fixed in a single transaction, songs without a file are only flagged
+    /// missing.
+    pub fn check_library(&self, repair: bool) -> Result<IntegrityReport, SongBaseError> {
+        let mut connection = self.conn.lock().unwrap();
+        let mut report = IntegrityReport::default();
+
+        check_schema(&connection, &mut report)?;
+        if report.count(IssueKind::Schema) > 0 {
+            // The other checks query these tables, they'd only add noise
+            return Ok(report);
+        }
+
+        let foreign_keys = check_foreign_keys(&connection, &mut report)?;
+        let dangling_links = check_dangling_links(&connection, &mut report)?;
+        let missing_files = check_missing_files(&connection, &mut report)?;
+        let duplicates = check_duplicate_paths(&connection, &mut report)?;
+
+        if repair {
+            let tx = connection.transaction()?;
+            let mut repaired = 0;
+
+            for (table, rowid) in foreign_keys {
+                repaired += tx.execute(
+                    &format!("DELETE FROM \"{}\" WHERE rowid = ?1", table),
+                    [rowid],
+                )?;
+            }
+            for (playlist_id, song_id) in dangling_links {
+                repaired += tx.execute(
+                    "DELETE FROM playlist_song_link WHERE playlist_id = ?1 AND song_id = ?2",
+                    [playlist_id, song_id],
+                )?;
+            }
+            for (kept_id, duplicate_id) in duplicates {
+                tx.execute(
+                    "INSERT OR IGNORE INTO playlist_song_link (playlist_id, song_id, position)
+                    SELECT playlist_id, ?1, position FROM playlist_song_link WHERE song_id = ?2",
+                    [kept_id, duplicate_id],
+                )?;
+                repaired += tx.execute("DELETE FROM songs WHERE song_id = ?1", [duplicate_id])?;
+            }
+            // Flagged like a rescan does, so plays, links and ratings stay
+            // around in case the file comes back
+            for song_id in missing_files {
+                repaired +=
+                    tx.execute("UPDATE songs SET missing = 1 WHERE song_id = ?1", [song_id])?;
+            }
+
+            tx.commit()?;
+            report.repaired = Some(repaired);
+        }
+
+        Ok(report)
+    }
+}
+
+fn check_schema(conn: &Connection, report: &mut IntegrityReport) -> Result<(), SongBaseError> {
+    let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        report.push(IssueKind::Schema, integrity);
+    }
+
+    let version = migrations::current_version(conn)?;
+    if version != migrations::latest_version() {
+        report.push(
+            IssueKind::Schema,
+            format!(
+                "schema version is {}, expected {}",
+                version,
+                migrations::latest_version()
+            ),
+        );
+    }
+
+    for (table, columns) in EXPECTED_SCHEMA {
+        let mut table_info = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
+        let found: Vec<String> = table_info
+            .query_map([], |row| row.get("name"))?
+            .filter_map(|column| column.ok())
+            .collect();
+
+        if found.is_empty() {
+            report.push(IssueKind::Schema, format!("table {} is missing", table));
+            continue;
+        }
+        for column in columns
+            .iter()
+            .filter(|column| !found.iter().any(|f| f == *column))
+        {
+            report.push(
+                IssueKind::Schema,
+                format!("column {}.{} is missing", table, column),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Violations outside `playlist_song_link`, which has its own check.
+fn check_foreign_keys(
+    conn: &Connection,
+    report: &mut IntegrityReport,
+) -> Result<Vec<(String, i64)>, SongBaseError> {
+    let mut fk_check = conn.prepare("PRAGMA foreign_key_check")?;
+    let violations: Vec<(String, i64, String)> = fk_check
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .filter_map(|violation| violation.ok())
+        .filter(|(table, _, _)| table != "playlist_song_link")
+        .collect();
+
+    Ok(violations
+        .into_iter()
+        .map(|(table, rowid, parent)| {
+            report.push(
+                IssueKind::ForeignKey,
+                format!("{} row {} points to a missing {}", table, rowid, parent),
+            );
+            (table, rowid)
+        })
+        .collect())
+}
+
+fn check_dangling_links(
+    conn: &Connection,
+    report: &mut IntegrityReport,
+) -> Result<Vec<(u32, u32)>, SongBaseError> {
+    let mut link_query = conn.prepare(
+        "SELECT link.playlist_id, link.song_id,
+            playlists.playlist_id IS NULL AS no_playlist,
+            songs.song_id IS NULL AS no_song
+        FROM playlist_song_link AS link
+        LEFT JOIN playlists ON playlists.playlist_id = link.playlist_id
+        LEFT JOIN songs ON songs.song_id = link.song_id
+        WHERE playlists.playlist_id IS NULL OR songs.song_id IS NULL",
+    )?;
+
+    let links: Vec<(u32, u32, bool, bool)> = link_query
+        .query_map([], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })?
+        .filter_map(|link| link.ok())
+        .collect();
+
+    Ok(links
+        .into_iter()
+        .map(|(playlist_id, song_id, no_playlist, no_song)| {
+            let missing = match (no_playlist, no_song) {
+                (true, true) => "playlist and song",
+                (true, false) => "playlist",
+                _ => "song",
+            };
+            report.push(
+                IssueKind::DanglingLink,
+                format!(
+                    "playlist {} -> song {} has no {}",
+                    playlist_id, song_id, missing
+                ),
+            );
+            (playlist_id, song_id)
+        })
+        .collect())
+}
+
+/// Songs whose file is gone but that aren't flagged missing yet.
+fn check_missing_files(
+    conn: &Connection,
+    report: &mut IntegrityReport,
+) -> Result<Vec<u32>, SongBaseError> {
+    let mut song_query = conn.prepare("SELECT song_id, song_path FROM songs WHERE NOT missing")?;
+    let songs: Vec<(u32, String)> = song_query
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .filter_map(|song| song.ok())
+        .collect();
+
+    Ok(songs
+        .into_iter()
+        .filter(|(_, song_path)| !Path::new(song_path).is_file())
+        .map(|(song_id, song_path)| {
+            report.push(
+                IssueKind::MissingFile,
+                format!("song {}: {}", song_id, song_path),
+            );
+            song_id
+        })
+        .collect())
+}
+
+/// Returns `(kept, duplicate)` pairs, the lowest id of each path is kept.
+fn check_duplicate_paths(
+    conn: &Connection,
+    report: &mut IntegrityReport,
+) -> Result<Vec<(u32, u32)>, SongBaseError> {
+    let mut duplicate_query = conn.prepare(
+        "SELECT kept.song_id, songs.song_id, songs.song_path
+        FROM songs
+        JOIN (SELECT MIN(song_id) AS song_id, song_path FROM songs GROUP BY song_path) AS kept
+            ON kept.song_path = songs.song_path AND kept.song_id != songs.song_id",
+    )?;
+
+    let duplicates: Vec<(u32, u32, String)> = duplicate_query
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .filter_map(|duplicate| duplicate.ok())
+        .collect();
+
+    Ok(duplicates
+        .into_iter()
+        .map(|(kept_id, song_id, song_path)| {
+            report.push(
+                IssueKind::DuplicatePath,
+                format!("song {} duplicates {}: {}", song_id, kept_id, song_path),
+            );
+            (kept_id, song_id)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn query_u32(song_base: &SongBase, sql: &str) -> u32 {
+        let connection = song_base.conn.lock().unwrap();
+        connection.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn fix_flags_missing_files_and_keeps_their_history() {
+        let (sender, _receiver) = mpsc::channel();
+        let song_base = SongBase::init(SongBase::IN_MEMORY, sender).unwrap();
+        song_base
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO songs (song_id, song_name, song_path)
+                VALUES (1, 'gone.mp3', '/nonexistent/gone.mp3')",
+                [],
+            )
+            .unwrap();
+        let playlist_id = song_base.create_playlist("Kept".to_string()).unwrap();
+        song_base.add_playlist_song(playlist_id, vec![1]).unwrap();
+        song_base.record_play(1, 1000).unwrap();
+
+        let report = song_base.check_library(true).unwrap();
+        assert_eq!(report.count(IssueKind::MissingFile), 1);
+        assert_eq!(report.repaired, Some(1));
+
+        assert_eq!(
+            query_u32(&song_base, "SELECT missing FROM songs WHERE song_id = 1"),
+            1
+        );
+        assert_eq!(
+            query_u32(&song_base, "SELECT COUNT(*) FROM playlist_song_link"),
+            1
+        );
+        assert_eq!(query_u32(&song_base, "SELECT COUNT(*) FROM plays"), 1);
+
+        // Already flagged, so it's no longer a problem
+        let report = song_base.check_library(false).unwrap();
+        assert_eq!(report.count(IssueKind::MissingFile), 0);
+    }
+}
@@ -0,0 +1,123 @@
+use rusqlite::{params, OptionalExtension};
+
+use super::SongBase;
+use crate::{error::SongBaseError, song::Playlist};
+
+/// Labels are matched case-insensitively, so they're stored lowercased.
+/// Commands split on spaces and commas, so a label can't hold either.
+fn label_name(label: &str) -> Result<String, SongBaseError> {
+    let label = label.trim().to_lowercase();
+    if label.is_empty() || label.contains(|ch: char| ch.is_whitespace() || ch == ',') {
+        return Err(SongBaseError::InvalidLabel);
+    }
+    Ok(label)
+}
+
+impl SongBase {
+    /// Puts `label` on the songs, creating it when it's new. Returns how many
+    /// of them didn't have it yet.
+    pub fn add_label(&self, label: &str, song_ids: &[u32]) -> Result<usize, SongBaseError> {
+        let label = label_name(label)?;
+        let mut connection = self.conn.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        tx.execute(
+            "INSERT OR IGNORE INTO labels (label_name) VALUES (?1)",
+            [&label],
+        )?;
+        let mut labelled = 0;
+        for song_id in song_ids {
+            labelled += tx.execute(
+                "INSERT OR IGNORE INTO song_labels (label_id, song_id)
+                SELECT label_id, ?2 FROM labels WHERE label_name = ?1",
+                params![label, song_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(labelled)
+    }
+
+    /// Takes `label` off the songs, or off every song and out of the library
+    /// when `song_ids` is `None`. Returns how many songs lost it.
+    pub fn remove_label(
+        &self,
+        label: &str,
+        song_ids: Option<&[u32]>,
+    ) -> Result<usize, SongBaseError> {
+        let label = label_name(label)?;
+        let mut connection = self.conn.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        let label_id: u32 = tx
+            .query_row(
+                "SELECT label_id FROM labels WHERE label_name = ?1",
+                [&label],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(SongBaseError::EntryNotFound)?;
+
+        let removed = match song_ids {
+            Some(song_ids) => {
+                let mut removed = 0;
+                for song_id in song_ids {
+                    removed += tx.execute(
+                        "DELETE FROM song_labels WHERE label_id = ?1 AND song_id = ?2",
+                        [label_id, *song_id],
+                    )?;
+                }
+                removed
+            }
+            None => {
+                let removed =
+                    tx.execute("DELETE FROM song_labels WHERE label_id = ?1", [label_id])?;
+                tx.execute("DELETE FROM labels WHERE label_id = ?1", [label_id])?;
+                removed
+            }
+        };
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Every label with how many songs carry it, by name.
+    pub fn get_labels(&self) -> Result<Vec<(String, u32)>, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        let mut label_query = connection.prepare(&format!(
+            "SELECT label_name, COUNT(songs.song_id) FROM labels
+            LEFT JOIN song_labels ON song_labels.label_id = labels.label_id
+            LEFT JOIN songs ON songs.song_id = song_labels.song_id AND {}
+            GROUP BY labels.label_id
+            ORDER BY label_name",
+            Self::LISTED_SQL
+        ))?;
+
+        let labels = label_query
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .filter_map(|label| label.ok())
+            .collect();
+        Ok(labels)
+    }
+
+    /// The songs carrying `label`, by name, as a playlist named after it.
+    pub fn get_label(&self, label: &str) -> Result<Playlist, SongBaseError> {
+        let label = label_name(label)?;
+        let connection = self.conn.lock().unwrap();
+        let mut label_query = connection.prepare(&format!(
+            "SELECT songs.* FROM songs
+            JOIN song_labels ON song_labels.song_id = songs.song_id
+            JOIN labels ON labels.label_id = song_labels.label_id
+            WHERE labels.label_name = ?1 AND {}
+            ORDER BY {} COLLATE NOCASE",
+            Self::LISTED_SQL,
+            Self::DISPLAY_NAME_SQL
+        ))?;
+
+        let mut songs = Playlist::new(&label);
+        label_query
+            .query_map([&label], Self::song_from_row)?
+            .flatten()
+            .flatten()
+            .for_each(|song| songs.add_song(song));
+        Ok(songs)
+    }
+}
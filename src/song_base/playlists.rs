@@ -0,0 +1,464 @@
+use std::{fmt::Display, fs, path::Path};
+
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+
+use super::SongBase;
+use crate::{
+    error::{SongBaseError, SongError},
+    m3u,
+    song::Song,
+};
+
+/// What `import_playlist` made of an M3U file.
+#[derive(Debug, PartialEq)]
+pub struct PlaylistImport {
+    pub playlist_id: u8,
+    pub playlist_name: String,
+    pub songs: usize,
+    /// Songs that weren't in the library before.
+    pub created: usize,
+    /// Entries with no playable file behind them, as written in the file.
+    pub unresolved: Vec<String>,
+}
+
+impl Display for PlaylistImport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Imported {} ({}): {} Songs, {} New to the Library, {} Unresolved",
+            self.playlist_name,
+            self.playlist_id,
+            self.songs,
+            self.created,
+            self.unresolved.len()
+        )
+    }
+}
+
+impl SongBase {
+    /// Creates a playlist named after the M3U file with the songs it lists,
+    /// adding the ones the library doesn't know yet. All of it or nothing
+    /// goes in, so a failed import can be retried.
+    pub fn import_playlist(&self, path: &Path) -> Result<PlaylistImport, SongBaseError> {
+        let bytes = fs::read(path).map_err(|_| SongBaseError::InvalidPath)?;
+        let playlist_dir = path.parent().unwrap_or(Path::new(""));
+        let playlist_name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .ok_or(SongBaseError::InvalidPath)?;
+
+        let mut connection = self.conn.lock().unwrap();
+        let tx = connection.transaction()?;
+        let playlist_id = Self::insert_playlist(&tx, &playlist_name)?;
+        let mut import = PlaylistImport {
+            playlist_id,
+            playlist_name,
+            songs: 0,
+            created: 0,
+            unresolved: Vec::new(),
+        };
+
+        let mut song_ids = Vec::new();
+        for entry in m3u::entries(&m3u::decode(&bytes, path)) {
+            // Stored paths are canonical, see `scan_songs`
+            let song_path = match m3u::resolve(&entry, playlist_dir).canonicalize() {
+                Ok(song_path) if Song::is_valid_song_path(&song_path) => song_path,
+                _ => {
+                    import.unresolved.push(entry);
+                    continue;
+                }
+            };
+            let song_path = song_path.to_string_lossy();
+
+            // A merged duplicate stands for the copy it was merged into
+            let known: Option<u32> = tx
+                .query_row(
+                    "SELECT COALESCE(merged_into, song_id) FROM songs WHERE song_path = ?1",
+                    [&song_path],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let song_id = match known {
+                Some(song_id) => song_id,
+                None => {
+                    let song_name = Path::new(song_path.as_ref())
+                        .file_name()
+                        .map_or(entry.clone(), |name| name.to_string_lossy().to_string());
+                    import.created += 1;
+                    Self::create_song(&tx, &song_name, &song_path)?
+                }
+            };
+            song_ids.push(song_id);
+        }
+
+        import.songs = Self::link_songs(&tx, playlist_id, song_ids)?;
+        tx.commit()?;
+        Ok(import)
+    }
+
+    /// The songs of a manual playlist that can be played, in order. These
+    /// are the ones `playlist view` numbers, so positions count over them;
+    /// songs whose file is gone are flagged missing and left out.
+    pub(super) fn playlist_songs(
+        conn: &Connection,
+        playlist_id: u8,
+    ) -> Result<Vec<Song>, SongBaseError> {
+        let mut songs_query = conn.prepare(
+            "SELECT songs.* FROM playlist_song_link JOIN songs USING (song_id)
+            WHERE playlist_id = ?1 ORDER BY position",
+        )?;
+        let rows: Vec<_> = songs_query
+            .query_map([playlist_id], |row| {
+                Ok((row.get::<_, u32>("song_id")?, Self::song_from_row(row)?))
+            })?
+            .flatten()
+            .collect();
+
+        let mut songs = Vec::with_capacity(rows.len());
+        for (song_id, song) in rows {
+            match song {
+                Ok(song) => songs.push(song),
+                // Kept with its playlist links in case the file comes back
+                Err(SongError::InvalidSongPath) => {
+                    conn.execute("UPDATE songs SET missing = 1 WHERE song_id = ?1", [song_id])?;
+                }
+                Err(_) => (),
+            }
+        }
+        Ok(songs)
+    }
+
+    /// Takes the songs at the given 1-based positions out of the playlist.
+    /// Returns how many were removed, positions past the end are ignored.
+    pub fn remove_playlist_songs(
+        &self,
+        playlist_id: u8,
+        positions: &[usize],
+    ) -> Result<usize, SongBaseError> {
+        let mut connection = self.conn.lock().unwrap();
+        let tx = connection.transaction()?;
+        Self::check_manual(&tx, playlist_id)?;
+
+        let shown = shown_order(&tx, playlist_id)?;
+        let removed: Vec<u32> = shown
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| positions.contains(&(index + 1)))
+            .map(|(_, song_id)| song_id)
+            .collect();
+        for song_id in &removed {
+            tx.execute(
+                "DELETE FROM playlist_song_link WHERE playlist_id = ?1 AND song_id = ?2",
+                params![playlist_id, song_id],
+            )?;
+        }
+        let mut kept = playlist_order(&tx, playlist_id)?;
+        kept.retain(|song_id| !removed.contains(song_id));
+        set_order(&tx, playlist_id, &kept)?;
+
+        tx.commit()?;
+        Ok(removed.len())
+    }
+
+    /// Moves the song at 1-based position `from` to `to`, shifting the ones
+    /// in between.
+    pub fn move_playlist_song(
+        &self,
+        playlist_id: u8,
+        from: usize,
+        to: usize,
+    ) -> Result<(), SongBaseError> {
+        let mut connection = self.conn.lock().unwrap();
+        let tx = connection.transaction()?;
+        Self::check_manual(&tx, playlist_id)?;
+
+        let shown = shown_order(&tx, playlist_id)?;
+        if from == 0 || to == 0 || from > shown.len() || to > shown.len() {
+            return Err(SongBaseError::EntryNotFound);
+        }
+        let (song_id, next_to) = (shown[from - 1], shown[to - 1]);
+
+        // Songs that aren't shown keep their place between the others
+        let mut order = playlist_order(&tx, playlist_id)?;
+        order.retain(|&other| other != song_id);
+        let next_to_index = order
+            .iter()
+            .position(|&other| other == next_to)
+            .unwrap_or(0);
+        match from < to {
+            true => order.insert(next_to_index + 1, song_id),
+            false => order.insert(next_to_index, song_id),
+        }
+        set_order(&tx, playlist_id, &order)?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn rename_playlist(
+        &self,
+        playlist_id: u8,
+        playlist_name: &str,
+    ) -> Result<(), SongBaseError> {
+        let renamed = self
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE playlists SET playlist_name = ?2 WHERE playlist_id = ?1",
+                params![playlist_id, playlist_name],
+            )
+            .map_err(name_error)?;
+        match renamed {
+            0 => Err(SongBaseError::EntryNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes the playlist, its songs stay in the library.
+    pub fn delete_playlist(&self, playlist_id: u8) -> Result<(), SongBaseError> {
+        let mut connection = self.conn.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        tx.execute(
+            "DELETE FROM playlist_song_link WHERE playlist_id = ?1",
+            [playlist_id],
+        )?;
+        let deleted = tx.execute(
+            "DELETE FROM playlists WHERE playlist_id = ?1",
+            [playlist_id],
+        )?;
+        if deleted == 0 {
+            return Err(SongBaseError::EntryNotFound);
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Copies the playlist (its rule, for a smart one) under a new name.
+    /// Returns the copy's id.
+    pub fn duplicate_playlist(
+        &self,
+        playlist_id: u8,
+        playlist_name: &str,
+    ) -> Result<u8, SongBaseError> {
+        let mut connection = self.conn.lock().unwrap();
+        let tx = connection.transaction()?;
+
+        let copy_id: u8 = tx
+            .query_row(
+                "INSERT INTO playlists (playlist_name, playlist_type, playlist_rule)
+                SELECT ?2, playlist_type, playlist_rule FROM playlists WHERE playlist_id = ?1
+                RETURNING playlist_id",
+                params![playlist_id, playlist_name],
+                |row| row.get(0),
+            )
+            .map_err(|err| match err {
+                rusqlite::Error::QueryReturnedNoRows => SongBaseError::EntryNotFound,
+                err => name_error(err),
+            })?;
+        tx.execute(
+            "INSERT INTO playlist_song_link (playlist_id, song_id, position)
+            SELECT ?2, song_id, position FROM playlist_song_link WHERE playlist_id = ?1",
+            [playlist_id, copy_id],
+        )?;
+
+        tx.commit()?;
+        Ok(copy_id)
+    }
+}
+
+/// The ids of the songs `playlist view` shows, first position first.
+fn shown_order(conn: &Connection, playlist_id: u8) -> Result<Vec<u32>, SongBaseError> {
+    let songs = SongBase::playlist_songs(conn, playlist_id)?;
+    Ok(songs.iter().map(|song| song.song_id).collect())
+}
+
+/// The playlist's song ids, first position first, shown or not.
+fn playlist_order(conn: &Connection, playlist_id: u8) -> Result<Vec<u32>, SongBaseError> {
+    let mut order_query = conn.prepare(
+        "SELECT song_id FROM playlist_song_link WHERE playlist_id = ?1 ORDER BY position",
+    )?;
+    let order = order_query
+        .query_map([playlist_id], |row| row.get(0))?
+        .filter_map(|song_id| song_id.ok())
+        .collect();
+    Ok(order)
+}
+
+/// Numbers the songs 1, 2, 3.. in the order given.
+fn set_order(conn: &Connection, playlist_id: u8, song_ids: &[u32]) -> Result<(), SongBaseError> {
+    let mut position_query = conn.prepare(
+        "UPDATE playlist_song_link SET position = ?3 WHERE playlist_id = ?1 AND song_id = ?2",
+    )?;
+    for (index, song_id) in song_ids.iter().enumerate() {
+        position_query.execute(params![playlist_id, song_id, index + 1])?;
+    }
+    Ok(())
+}
+
+/// Playlist names are unique.
+fn name_error(err: rusqlite::Error) -> SongBaseError {
+    match err.sqlite_error_code() {
+        Some(ErrorCode::ConstraintViolation) => SongBaseError::NameAlreadyExist,
+        _ => SongBaseError::DatabaseError(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        path::PathBuf,
+        process,
+        sync::mpsc::{self, Receiver},
+    };
+
+    use super::*;
+    use crate::player::PlayerAction;
+
+    struct Library {
+        song_base: SongBase,
+        playlist_id: u8,
+        dir: PathBuf,
+        _receiver: Receiver<PlayerAction>,
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// A library with songs 1..=names.len() in one playlist, in that order.
+    /// Songs whose name starts with `gone` have no file behind them.
+    fn library_with(test: &str, names: &[&str]) -> Library {
+        let dir = env::temp_dir().join(format!("bz_player_{}_{}", test, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let song_base = SongBase::init(SongBase::IN_MEMORY, sender).unwrap();
+        let playlist_id = song_base.create_playlist("Order".to_string()).unwrap();
+        for (index, name) in names.iter().enumerate() {
+            let song_path = dir.join(name);
+            if !name.starts_with("gone") {
+                fs::write(&song_path, b"").unwrap();
+            }
+            song_base
+                .conn
+                .lock()
+                .unwrap()
+                .execute(
+                    "INSERT INTO songs (song_id, song_name, song_path) VALUES (?1, ?2, ?3)",
+                    params![index + 1, name, song_path.to_string_lossy()],
+                )
+                .unwrap();
+        }
+        let song_ids = (1..=names.len() as u32).collect();
+        song_base.add_playlist_song(playlist_id, song_ids).unwrap();
+        Library {
+            song_base,
+            playlist_id,
+            dir,
+            _receiver: receiver,
+        }
+    }
+
+    fn names(song_base: &SongBase, playlist_id: u8) -> Vec<String> {
+        let playlist = song_base.get_playlist(playlist_id).unwrap();
+        playlist
+            .songs
+            .iter()
+            .map(|song| song.song_name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn set_order_numbers_from_one() {
+        let library = library_with("set_order", &["a.mp3", "b.mp3"]);
+        let (song_base, playlist_id) = (&library.song_base, library.playlist_id);
+        set_order(&song_base.conn.lock().unwrap(), playlist_id, &[2, 1]).unwrap();
+        assert_eq!(names(song_base, playlist_id), ["b.mp3", "a.mp3"]);
+        assert_eq!(
+            playlist_order(&song_base.conn.lock().unwrap(), playlist_id).unwrap(),
+            [2, 1]
+        );
+    }
+
+    #[test]
+    fn remove_and_move_count_the_shown_songs() {
+        let library = library_with(
+            "remove_move",
+            &["a.mp3", "gone.mp3", "b.mp3", "c.mp3", "d.mp3"],
+        );
+        let (song_base, playlist_id) = (&library.song_base, library.playlist_id);
+        assert_eq!(
+            names(song_base, playlist_id),
+            ["a.mp3", "b.mp3", "c.mp3", "d.mp3"]
+        );
+
+        assert_eq!(
+            song_base
+                .remove_playlist_songs(playlist_id, &[2, 9])
+                .unwrap(),
+            1
+        );
+        assert_eq!(names(song_base, playlist_id), ["a.mp3", "c.mp3", "d.mp3"]);
+
+        song_base.move_playlist_song(playlist_id, 3, 1).unwrap();
+        assert_eq!(names(song_base, playlist_id), ["d.mp3", "a.mp3", "c.mp3"]);
+        song_base.move_playlist_song(playlist_id, 1, 2).unwrap();
+        assert_eq!(names(song_base, playlist_id), ["a.mp3", "d.mp3", "c.mp3"]);
+        assert!(song_base.move_playlist_song(playlist_id, 4, 1).is_err());
+
+        // The missing song keeps its link, and `d` went right after `a`
+        assert_eq!(
+            playlist_order(&song_base.conn.lock().unwrap(), playlist_id).unwrap(),
+            [1, 5, 2, 4]
+        );
+    }
+
+    #[test]
+    fn import_reads_latin1_playlists_in_one_go() {
+        let library = library_with("import", &["café.mp3"]);
+        let song_base = &library.song_base;
+        let m3u_path = library.dir.join("Mix.m3u");
+        fs::write(&m3u_path, b"#EXTM3U\ncaf\xe9.mp3\ngone.mp3\n").unwrap();
+
+        let import = song_base.import_playlist(&m3u_path).unwrap();
+        assert_eq!(import.songs, 1);
+        assert_eq!(import.created, 0);
+        assert_eq!(import.unresolved, ["gone.mp3"]);
+        assert_eq!(names(song_base, import.playlist_id), ["café.mp3"]);
+
+        assert!(matches!(
+            song_base.import_playlist(&m3u_path),
+            Err(SongBaseError::NameAlreadyExist)
+        ));
+    }
+
+    #[test]
+    fn smart_playlists_run_their_rule() {
+        let library = library_with("smart", &["a.mp3", "b.mp3", "c.mp3", "d.mp3"]);
+        let song_base = &library.song_base;
+
+        let picked = song_base
+            .create_smart_playlist("Picked".to_string(), "-file:b limit:5".to_string())
+            .unwrap();
+        assert_eq!(names(song_base, picked), ["a.mp3", "c.mp3", "d.mp3"]);
+        let shuffled = song_base
+            .create_smart_playlist("Shuffled".to_string(), "sort:random limit:2".to_string())
+            .unwrap();
+        assert_eq!(names(song_base, shuffled).len(), 2);
+
+        assert!(matches!(
+            song_base.add_playlist_song(picked, vec![2]),
+            Err(SongBaseError::SmartPlaylist)
+        ));
+        assert!(matches!(
+            song_base.create_smart_playlist("Broken".to_string(), "@nowhere".to_string()),
+            Err(SongBaseError::InvalidQuery(_))
+        ));
+    }
+}
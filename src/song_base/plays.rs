@@ -0,0 +1,120 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rusqlite::params;
+
+use super::SongBase;
+use crate::{error::SongBaseError, song::PlayRecord};
+
+impl SongBase {
+    /// Adds a play to the history and bumps the song's play count.
+    pub fn record_play(&self, song_id: u32, listened_ms: u64) -> Result<(), SongBaseError> {
+        let played_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+
+        let mut connection = self.conn.lock().unwrap();
+        let tx = connection.transaction()?;
+        tx.execute(
+            "INSERT INTO plays (song_id, played_at, listened_ms) VALUES (?1, ?2, ?3)",
+            params![song_id, played_at, listened_ms],
+        )?;
+        tx.execute(
+            "UPDATE songs SET play_count = play_count + 1, last_played = ?2 WHERE song_id = ?1",
+            params![song_id, played_at],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn record_skip(&self, song_id: u32) -> Result<(), SongBaseError> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE songs SET skip_count = skip_count + 1 WHERE song_id = ?1",
+            [song_id],
+        )?;
+        Ok(())
+    }
+
+    /// The latest `limit` plays, newest first.
+    pub fn get_history(&self, limit: usize) -> Result<Vec<PlayRecord>, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        let mut history_query = connection.prepare(&format!(
+            "SELECT songs.song_id, {} AS display_name,
+                strftime('%Y-%m-%d %H:%M', played_at, 'unixepoch', 'localtime') AS played_at,
+                listened_ms
+            FROM plays JOIN songs ON songs.song_id = plays.song_id
+            ORDER BY plays.played_at DESC, plays.play_id DESC LIMIT ?1",
+            Self::DISPLAY_NAME_SQL
+        ))?;
+
+        let history = history_query
+            .query_map([limit], |row| {
+                Ok(PlayRecord {
+                    song_id: row.get("song_id")?,
+                    display_name: row.get("display_name")?,
+                    played_at: row.get("played_at")?,
+                    listened_ms: row.get("listened_ms")?,
+                })
+            })?
+            .filter_map(|play| play.ok())
+            .collect();
+        Ok(history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::tags::Tags;
+
+    /// Names and details of what `query` finds, in order.
+    fn found(song_base: &SongBase, query: &str) -> Vec<(String, Option<String>)> {
+        song_base
+            .search(query)
+            .unwrap()
+            .into_iter()
+            .map(|hit| (hit.display_name, hit.detail))
+            .collect()
+    }
+
+    #[test]
+    fn plays_and_skips_count_sort_and_filter() {
+        let (sender, _receiver) = mpsc::channel();
+        let song_base = SongBase::init(SongBase::IN_MEMORY, sender).unwrap();
+        {
+            let connection = song_base.conn.lock().unwrap();
+            for name in ["a.mp3", "b.mp3", "c.mp3"] {
+                let song_path = format!("/music/{}", name);
+                SongBase::upsert_song(&connection, name, &song_path, &Tags::default(), None)
+                    .unwrap();
+            }
+        }
+        song_base.record_play(2, 1_000).unwrap();
+        song_base.record_play(2, 2_000).unwrap();
+        song_base.record_play(3, 3_000).unwrap();
+        song_base.record_skip(1).unwrap();
+
+        let detail = |text: &str| Some(text.to_string());
+        assert_eq!(
+            found(&song_base, "sort:-plays"),
+            [
+                ("b.mp3".to_string(), detail("2 plays")),
+                ("c.mp3".to_string(), detail("1 play")),
+                ("a.mp3".to_string(), detail("0 plays")),
+            ]
+        );
+        assert_eq!(
+            found(&song_base, "plays:0 skips:>0"),
+            [("a.mp3".to_string(), None)]
+        );
+
+        let history: Vec<_> = song_base
+            .get_history(10)
+            .unwrap()
+            .into_iter()
+            .map(|play| (play.song_id, play.listened_ms))
+            .collect();
+        assert_eq!(history, [(3, 3_000), (2, 2_000), (2, 1_000)]);
+    }
+}
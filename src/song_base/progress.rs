@@ -0,0 +1,124 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::player::PlayerAction;
+
+/// Gap between progress events, so a fast scan doesn't flood the channel.
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Handle on a running scan, kept by the UI to cancel it.
+#[derive(Debug)]
+pub struct ScanJob {
+    pub root: PathBuf,
+    cancel: Arc<AtomicBool>,
+}
+
+impl ScanJob {
+    /// Stops the scan at the next file. Songs it already wrote stay, but
+    /// nothing gets marked missing.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ScanProgress {
+    pub current_dir: PathBuf,
+    pub files_seen: usize,
+    pub added: usize,
+    pub skipped: usize,
+    pub errors: usize,
+}
+
+/// The scan's side of a `ScanJob`: counts what it does, sends the counts
+/// now and then, and tells it when to give up. Shared by every thread of the
+/// scan.
+pub(super) struct ScanReporter {
+    state: Mutex<ReportState>,
+    sender: Option<Sender<PlayerAction>>,
+    cancel: Arc<AtomicBool>,
+}
+
+struct ReportState {
+    progress: ScanProgress,
+    last_report: Instant,
+}
+
+impl ScanReporter {
+    pub(super) fn new(root: &Path, sender: Sender<PlayerAction>) -> (Self, ScanJob) {
+        let cancel = Arc::new(AtomicBool::new(false));
+        let job = ScanJob {
+            root: root.to_path_buf(),
+            cancel: Arc::clone(&cancel),
+        };
+        let reporter = Self {
+            state: Mutex::new(ReportState {
+                progress: ScanProgress::default(),
+                last_report: Instant::now(),
+            }),
+            sender: Some(sender),
+            cancel,
+        };
+        (reporter, job)
+    }
+
+    /// Counts without reporting and can't be cancelled, for the watcher.
+    pub(super) fn silent() -> Self {
+        Self {
+            state: Mutex::new(ReportState {
+                progress: ScanProgress::default(),
+                last_report: Instant::now(),
+            }),
+            sender: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub(super) fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn progress(&self) -> ScanProgress {
+        self.state.lock().unwrap().progress.clone()
+    }
+
+    pub(super) fn enter_dir(&self, dir: &Path) {
+        self.update(|progress| progress.current_dir = dir.to_path_buf());
+    }
+
+    pub(super) fn file_seen(&self) {
+        self.update(|progress| progress.files_seen += 1);
+    }
+
+    pub(super) fn added(&self, count: usize) {
+        self.update(|progress| progress.added += count);
+    }
+
+    pub(super) fn skipped(&self) {
+        self.update(|progress| progress.skipped += 1);
+    }
+
+    pub(super) fn error(&self) {
+        self.update(|progress| progress.errors += 1);
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ScanProgress)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state.progress);
+
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        if state.last_report.elapsed() >= REPORT_INTERVAL {
+            let _ = sender.send(PlayerAction::ScanProgress(state.progress.clone()));
+            state.last_report = Instant::now();
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use rusqlite::{params, OptionalExtension};
+
+use super::SongBase;
+use crate::{error::SongBaseError, song::Playlist, tags};
+
+/// Setting that has ratings written back into the files' tags.
+const WRITE_TAGS: &str = "rating_write_tags";
+
+impl SongBase {
+    /// Rates the song 1 to 5 stars, `None` clears the rating. The rating goes
+    /// into the file's tags too when `set_write_ratings` is on, either way
+    /// it wins over what rescans find in the file from then on.
+    pub fn set_rating(&self, song_id: u32, rating: Option<u8>) -> Result<(), SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        let song_path: String = connection
+            .query_row(
+                "UPDATE songs SET rating = ?2, rating_set = 1 WHERE song_id = ?1
+                RETURNING song_path",
+                params![song_id, rating],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or(SongBaseError::EntryNotFound)?;
+        drop(connection);
+
+        if self.get_write_ratings()? {
+            tags::write_rating(Path::new(&song_path), rating)
+                .map_err(SongBaseError::RatingNotWritten)?;
+        }
+        Ok(())
+    }
+
+    pub fn set_favorite(&self, song_id: u32, favorite: bool) -> Result<(), SongBaseError> {
+        let updated = self.conn.lock().unwrap().execute(
+            "UPDATE songs SET favorite = ?2 WHERE song_id = ?1",
+            params![song_id, favorite],
+        )?;
+        match updated {
+            0 => Err(SongBaseError::EntryNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    /// Every favorite, best rated first, as a playlist.
+    pub fn get_favorites(&self) -> Result<Playlist, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        let mut favorite_query = connection.prepare(&format!(
+            "SELECT * FROM songs WHERE favorite AND {}
+            ORDER BY rating DESC NULLS LAST, {} COLLATE NOCASE",
+            Self::LISTED_SQL,
+            Self::DISPLAY_NAME_SQL
+        ))?;
+
+        let mut favorites = Playlist::new("Favorites");
+        favorite_query
+            .query_map([], Self::song_from_row)?
+            .flatten()
+            .flatten()
+            .for_each(|song| favorites.add_song(song));
+        Ok(favorites)
+    }
+
+    pub fn get_write_ratings(&self) -> Result<bool, SongBaseError> {
+        let value: Option<String> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT setting_value FROM settings WHERE setting_key = ?1",
+                [WRITE_TAGS],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(value.is_some_and(|value| value == "1"))
+    }
+
+    /// Off by default, so the files are only touched when asked to.
+    pub fn set_write_ratings(&self, write: bool) -> Result<(), SongBaseError> {
+        self.set_setting(WRITE_TAGS, write.then_some("1"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::tags::Tags;
+
+    fn rating(song_base: &SongBase, song_id: u32) -> Option<u8> {
+        let connection = song_base.conn.lock().unwrap();
+        connection
+            .query_row(
+                "SELECT rating FROM songs WHERE song_id = ?1",
+                [song_id],
+                |row| row.get(0),
+            )
+            .unwrap()
+    }
+
+    /// What a rescan does with the file's tags, rated `stars` in its POPM.
+    fn rescan(song_base: &SongBase, song_path: &str, stars: Option<u8>) {
+        let tags = Tags {
+            rating: stars,
+            ..Tags::default()
+        };
+        let connection = song_base.conn.lock().unwrap();
+        SongBase::upsert_song(&connection, "song.mp3", song_path, &tags, None).unwrap();
+    }
+
+    #[test]
+    fn ratings_set_in_the_library_outlast_rescans() {
+        let (sender, _receiver) = mpsc::channel();
+        let song_base = SongBase::init(SongBase::IN_MEMORY, sender).unwrap();
+        rescan(&song_base, "/music/a.mp3", Some(4));
+        rescan(&song_base, "/music/b.mp3", None);
+        assert_eq!(rating(&song_base, 1), Some(4));
+
+        // Not set by hand yet, so the file still counts
+        rescan(&song_base, "/music/a.mp3", Some(3));
+        assert_eq!(rating(&song_base, 1), Some(3));
+
+        song_base.set_rating(1, None).unwrap();
+        song_base.set_rating(2, Some(2)).unwrap();
+        rescan(&song_base, "/music/a.mp3", Some(3));
+        rescan(&song_base, "/music/b.mp3", Some(5));
+        assert_eq!(rating(&song_base, 1), None);
+        assert_eq!(rating(&song_base, 2), Some(2));
+    }
+}
@@ -0,0 +1,314 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection};
+
+use super::{
+    progress::{ScanJob, ScanReporter},
+    walk::Walker,
+    SongBase,
+};
+use crate::{error::SongBaseError, player::PlayerAction, tags::Tags};
+
+/// Most songs written in one transaction, the lock is free in between.
+const WRITE_BATCH_SIZE: usize = 256;
+/// How long a batch waits to fill up before it's written anyway.
+const BATCH_WAIT: Duration = Duration::from_millis(200);
+
+/// Threads walking folders and reading tags. Mostly waiting on the disk, so
+/// more than there are cores doesn't hurt, and helps on network drives.
+pub(super) fn worker_count() -> usize {
+    thread::available_parallelism().map_or(4, |cores| cores.get()) * 2
+}
+
+/// Modification time and size of a file, compared between scans to tell
+/// whether its tags need reading again.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct FileStamp {
+    pub mtime_ms: i64,
+    pub size: i64,
+}
+
+impl FileStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            mtime_ms: modified.as_millis() as i64,
+            size: metadata.len() as i64,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ScanSummary {
+    pub added: usize,
+    pub updated: usize,
+    pub missing: usize,
+    pub unchanged: usize,
+    pub skipped_dirs: usize,
+    pub errors: usize,
+}
+
+impl Display for ScanSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Scan Done: {} Added, {} Updated, {} Missing, {} Unchanged, {} Folders Skipped, {} Errors",
+            self.added, self.updated, self.missing, self.unchanged, self.skipped_dirs, self.errors
+        )
+    }
+}
+
+pub(super) struct ScannedFile {
+    name: String,
+    path: String,
+    stamp: FileStamp,
+}
+
+impl ScannedFile {
+    pub(super) fn of(path: &Path) -> Option<Self> {
+        Some(Self {
+            name: path.file_name()?.to_string_lossy().to_string(),
+            path: path.to_string_lossy().to_string(),
+            stamp: FileStamp::of(path)?,
+        })
+    }
+}
+
+struct KnownSong {
+    song_id: u32,
+    stamp: Option<FileStamp>,
+    missing: bool,
+}
+
+enum Change {
+    Add(ScannedFile, Tags),
+    Update(ScannedFile, Tags),
+    Missing(u32),
+}
+
+impl SongBase {
+    /// Matches `song_path` against the path `?1` itself or anything inside
+    /// it, `?2` being the path separator.
+    pub(super) const UNDER_PATH_SQL: &'static str =
+        "(song_path = ?1 OR substr(song_path, 1, length(?1) + 1) = ?1 || ?2)";
+
+    /// Starts scanning `path` (the audio folder by default) in the
+    /// background. Progress and the final summary arrive over the sender.
+    pub fn scan_songs(&self, path: Option<String>) -> Result<ScanJob, SongBaseError> {
+        let path = match path {
+            Some(p) => PathBuf::from(p),
+            None => match dirs::audio_dir() {
+                Some(p) => p,
+                None => return Err(SongBaseError::InvalidPath),
+            },
+        };
+
+        // Stored paths are the key between scans, so they have to be absolute
+        let Ok(path) = path.canonicalize() else {
+            return Err(SongBaseError::InvalidPath);
+        };
+        self.add_root(&path)?;
+        let options = self.get_scan_options()?;
+
+        let connection = Arc::clone(&self.conn);
+        let sender_clone = self.sender.clone();
+        let (reporter, job) = ScanReporter::new(&path, self.sender.clone());
+
+        thread::spawn(move || {
+            let walker = Walker::new(&path, &options);
+            let result = Self::fetch_songs(&walker, &path, &connection, &reporter);
+            let _ = sender_clone.send(PlayerAction::ScanFinished(result));
+        });
+        Ok(job)
+    }
+
+    /// Brings the songs under `root` in line with the disk: new files are
+    /// added, changed ones get their tags read again and vanished ones are
+    /// marked missing. Files are synced while the walk is still finding more.
+    pub(super) fn fetch_songs(
+        walker: &Walker,
+        root: &Path,
+        conn: &Arc<Mutex<Connection>>,
+        reporter: &ScanReporter,
+    ) -> Result<ScanSummary, SongBaseError> {
+        let (file_sender, file_receiver) = mpsc::channel();
+        let mut summary = thread::scope(|scope| {
+            scope.spawn(move || walker.walk(root, reporter, file_sender));
+            Self::sync_files(root, file_receiver, conn, reporter)
+        })?;
+
+        let progress = reporter.progress();
+        summary.skipped_dirs = progress.skipped;
+        summary.errors = progress.errors;
+        Ok(summary)
+    }
+
+    /// Reconciles the songs stored at or under `root` with `files`, the ones
+    /// found there now. Tags are read on a pool of threads without holding
+    /// the lock, and the writes go in in batches so queries get a turn in
+    /// between.
+    pub(super) fn sync_files(
+        root: &Path,
+        files: impl IntoIterator<Item = ScannedFile>,
+        conn: &Arc<Mutex<Connection>>,
+        reporter: &ScanReporter,
+    ) -> Result<ScanSummary, SongBaseError> {
+        let known = Self::known_songs(root, conn)?;
+
+        let (read_sender, read_receiver) = mpsc::channel::<(ScannedFile, bool)>();
+        let (change_sender, change_receiver) = mpsc::channel();
+        let read_receiver = Mutex::new(read_receiver);
+
+        thread::scope(|scope| {
+            let writer = scope.spawn(|| Self::write_changes(change_receiver, conn, reporter));
+
+            for _ in 0..worker_count() {
+                let change_sender = change_sender.clone();
+                let read_receiver = &read_receiver;
+                scope.spawn(move || loop {
+                    let Ok((file, is_new)) = read_receiver.lock().unwrap().recv() else {
+                        break;
+                    };
+                    if reporter.is_cancelled() {
+                        continue;
+                    }
+                    let tags = Tags::read(Path::new(&file.path));
+                    let change = match is_new {
+                        true => Change::Add(file, tags),
+                        false => Change::Update(file, tags),
+                    };
+                    if change_sender.send(change).is_err() {
+                        // The writer failed, it reports why
+                        break;
+                    }
+                });
+            }
+
+            let mut unchanged = 0;
+            let mut seen = HashSet::new();
+            for file in files {
+                if reporter.is_cancelled() {
+                    break;
+                }
+                seen.insert(file.path.clone());
+                match known.get(&file.path) {
+                    Some(song) if !song.missing && song.stamp == Some(file.stamp) => unchanged += 1,
+                    Some(_) => {
+                        let _ = read_sender.send((file, false));
+                    }
+                    None => {
+                        let _ = read_sender.send((file, true));
+                    }
+                }
+            }
+            drop(read_sender);
+
+            // Only a full pass over the files tells what's gone
+            if !reporter.is_cancelled() {
+                for (song_path, song) in &known {
+                    if !song.missing && !seen.contains(song_path) {
+                        let _ = change_sender.send(Change::Missing(song.song_id));
+                    }
+                }
+            }
+            drop(change_sender);
+
+            let mut summary = writer.join().unwrap()?;
+            if reporter.is_cancelled() {
+                return Err(SongBaseError::ScanCancelled);
+            }
+            summary.unchanged = unchanged;
+            Ok(summary)
+        })
+    }
+
+    fn known_songs(
+        root: &Path,
+        conn: &Arc<Mutex<Connection>>,
+    ) -> Result<HashMap<String, KnownSong>, SongBaseError> {
+        let connection = conn.lock().unwrap();
+        let mut known_query = connection.prepare(&format!(
+            "SELECT song_id, song_path, file_mtime, file_size, missing FROM songs
+            WHERE {}",
+            Self::UNDER_PATH_SQL
+        ))?;
+        let known = known_query
+            .query_map(
+                params![root.to_string_lossy(), MAIN_SEPARATOR.to_string()],
+                |row| {
+                    let stamp = match (row.get(2)?, row.get(3)?) {
+                        (Some(mtime_ms), Some(size)) => Some(FileStamp { mtime_ms, size }),
+                        _ => None,
+                    };
+                    let song = KnownSong {
+                        song_id: row.get(0)?,
+                        stamp,
+                        missing: row.get(4)?,
+                    };
+                    Ok((row.get::<_, String>(1)?, song))
+                },
+            )?
+            .filter_map(|song| song.ok())
+            .collect();
+        Ok(known)
+    }
+
+    /// The only thread of a scan that writes, a transaction per batch.
+    fn write_changes(
+        changes: Receiver<Change>,
+        conn: &Arc<Mutex<Connection>>,
+        reporter: &ScanReporter,
+    ) -> Result<ScanSummary, SongBaseError> {
+        let mut summary = ScanSummary::default();
+        let mut batch = Vec::with_capacity(WRITE_BATCH_SIZE);
+
+        while let Ok(change) = changes.recv() {
+            batch.push(change);
+            let deadline = Instant::now() + BATCH_WAIT;
+            while batch.len() < WRITE_BATCH_SIZE {
+                match changes.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(change) => batch.push(change),
+                    Err(_) => break,
+                }
+            }
+
+            let mut added = 0;
+            let mut connection = conn.lock().unwrap();
+            let tx = connection.transaction()?;
+            for change in batch.drain(..) {
+                match change {
+                    Change::Add(file, tags) => {
+                        Self::upsert_song(&tx, &file.name, &file.path, &tags, Some(file.stamp))?;
+                        added += 1;
+                    }
+                    Change::Update(file, tags) => {
+                        Self::upsert_song(&tx, &file.name, &file.path, &tags, Some(file.stamp))?;
+                        summary.updated += 1;
+                    }
+                    Change::Missing(song_id) => {
+                        tx.execute("UPDATE songs SET missing = 1 WHERE song_id = ?1", [song_id])?;
+                        summary.missing += 1;
+                    }
+                }
+            }
+            tx.commit()?;
+            drop(connection);
+
+            summary.added += added;
+            reporter.added(added);
+        }
+        Ok(summary)
+    }
+}
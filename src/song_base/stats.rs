@@ -0,0 +1,259 @@
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+use super::SongBase;
+use crate::error::SongBaseError;
+
+/// Entries in each of the top lists.
+const TOP_COUNT: usize = 10;
+const DAY_SECS: i64 = 24 * 60 * 60;
+
+/// How far back listening stats look.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsPeriod {
+    Week,
+    Month,
+    Year,
+    All,
+}
+
+impl StatsPeriod {
+    pub fn parse(period: &str) -> Option<Self> {
+        match period.to_lowercase().as_str() {
+            "week" => Some(Self::Week),
+            "month" => Some(Self::Month),
+            "year" => Some(Self::Year),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+
+    /// Unix time the period starts at, `None` for all time.
+    fn since(&self) -> Option<i64> {
+        let days = match self {
+            Self::Week => 7,
+            Self::Month => 30,
+            Self::Year => 365,
+            Self::All => return None,
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs() as i64);
+        Some(now - days * DAY_SECS)
+    }
+}
+
+impl Display for StatsPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let period = match self {
+            Self::Week => "Last 7 Days",
+            Self::Month => "Last 30 Days",
+            Self::Year => "Last 365 Days",
+            Self::All => "All Time",
+        };
+        write!(f, "{}", period)
+    }
+}
+
+/// A song, artist or album and how much it was played.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TopEntry {
+    pub name: String,
+    pub plays: u64,
+    pub listened_ms: u64,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ListeningStats {
+    pub period: StatsPeriod,
+    pub plays: u64,
+    pub listened_ms: u64,
+    pub top_songs: Vec<TopEntry>,
+    pub top_artists: Vec<TopEntry>,
+    pub top_albums: Vec<TopEntry>,
+    /// Local time, Monday first.
+    pub plays_by_weekday: [u64; 7],
+    /// Local time, midnight first.
+    pub plays_by_hour: [u64; 24],
+    /// Most days in a row with at least one play.
+    pub longest_streak_days: u32,
+}
+
+impl SongBase {
+    /// Everything `stats` shows, over the plays in `period`.
+    pub fn get_stats(&self, period: StatsPeriod) -> Result<ListeningStats, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        let since = period.since().unwrap_or(i64::MIN);
+
+        let (plays, listened_ms) = connection.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(listened_ms), 0) FROM plays WHERE played_at >= ?1",
+            [since],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        let mut plays_by_weekday = [0; 7];
+        // strftime counts from Sunday
+        for (weekday, count) in count_by(&connection, "%w", since)? {
+            plays_by_weekday[(weekday + 6) % 7] = count;
+        }
+        let mut plays_by_hour = [0; 24];
+        for (hour, count) in count_by(&connection, "%H", since)? {
+            plays_by_hour[hour] = count;
+        }
+
+        Ok(ListeningStats {
+            period,
+            plays,
+            listened_ms,
+            top_songs: top_entries(&connection, Self::DISPLAY_NAME_SQL, "songs.song_id", since)?,
+            top_artists: top_entries(&connection, "songs.artist", "songs.artist", since)?,
+            top_albums: top_entries(
+                &connection,
+                "albums.album_name || ' by ' || albums.album_artist",
+                "albums.album_id",
+                since,
+            )?,
+            plays_by_weekday,
+            plays_by_hour,
+            longest_streak_days: longest_streak(&connection, since)?,
+        })
+    }
+}
+
+/// The most played groups of `group_sql`, shown as `name_sql`. Plays
+/// without a name (no artist or album tag) are left out.
+fn top_entries(
+    conn: &Connection,
+    name_sql: &str,
+    group_sql: &str,
+    since: i64,
+) -> Result<Vec<TopEntry>, SongBaseError> {
+    let mut top_query = conn.prepare(&format!(
+        "SELECT {} AS name, COUNT(*) AS plays, COALESCE(SUM(listened_ms), 0) AS listened_ms
+        FROM plays JOIN songs ON songs.song_id = plays.song_id
+            LEFT JOIN albums ON albums.album_id = songs.album_id
+        WHERE played_at >= ?1
+        GROUP BY {}
+        HAVING name IS NOT NULL
+        ORDER BY plays DESC, listened_ms DESC, name COLLATE NOCASE
+        LIMIT ?2",
+        name_sql, group_sql
+    ))?;
+
+    let entries = top_query
+        .query_map(params![since, TOP_COUNT], |row| {
+            Ok(TopEntry {
+                name: row.get("name")?,
+                plays: row.get("plays")?,
+                listened_ms: row.get("listened_ms")?,
+            })
+        })?
+        .filter_map(|entry| entry.ok())
+        .collect();
+    Ok(entries)
+}
+
+/// Plays grouped by a numeric local time `strftime` field.
+fn count_by(
+    conn: &Connection,
+    format: &str,
+    since: i64,
+) -> Result<Vec<(usize, u64)>, SongBaseError> {
+    let mut count_query = conn.prepare(
+        "SELECT CAST(strftime(?1, played_at, 'unixepoch', 'localtime') AS INTEGER) AS slot,
+            COUNT(*)
+        FROM plays WHERE played_at >= ?2
+        GROUP BY slot",
+    )?;
+
+    let counts = count_query
+        .query_map(params![format, since], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .filter_map(|count| count.ok())
+        .collect();
+    Ok(counts)
+}
+
+fn longest_streak(conn: &Connection, since: i64) -> Result<u32, SongBaseError> {
+    let mut day_query = conn.prepare(
+        "SELECT DISTINCT CAST(julianday(played_at, 'unixepoch', 'localtime', 'start of day')
+            AS INTEGER) AS day
+        FROM plays WHERE played_at >= ?1
+        ORDER BY day",
+    )?;
+    let days: Vec<i64> = day_query
+        .query_map([since], |row| row.get(0))?
+        .filter_map(|day| day.ok())
+        .collect();
+
+    let (mut longest, mut current, mut previous) = (0, 0, None);
+    for day in days {
+        current = match previous == Some(day - 1) {
+            true => current + 1,
+            false => 1,
+        };
+        longest = longest.max(current);
+        previous = Some(day);
+    }
+    Ok(longest)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    /// Unix time of local noon on `date`.
+    fn noon(conn: &Connection, date: &str) -> i64 {
+        conn.query_row(
+            "SELECT CAST(strftime('%s', ?1 || ' 12:00', 'utc') AS INTEGER)",
+            [date],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn longest_streak_counts_days_in_a_row() {
+        let (sender, _receiver) = mpsc::channel();
+        let song_base = SongBase::init(SongBase::IN_MEMORY, sender).unwrap();
+        let connection = song_base.conn.lock().unwrap();
+        assert_eq!(longest_streak(&connection, i64::MIN).unwrap(), 0);
+
+        connection
+            .execute(
+                "INSERT INTO songs (song_id, song_name, song_path) VALUES (1, 'a.mp3', '/a.mp3')",
+                [],
+            )
+            .unwrap();
+        let dates = [
+            "2024-02-27",
+            "2024-02-28",
+            "2024-02-29",
+            "2024-02-29",
+            "2024-03-01",
+            "2024-03-03",
+            "2024-03-05",
+            "2024-03-06",
+        ];
+        for date in dates {
+            connection
+                .execute(
+                    "INSERT INTO plays (song_id, played_at, listened_ms) VALUES (1, ?1, 0)",
+                    [noon(&connection, date)],
+                )
+                .unwrap();
+        }
+
+        // Across the leap day, and twice on one day still counts once
+        assert_eq!(longest_streak(&connection, i64::MIN).unwrap(), 4);
+        let since = noon(&connection, "2024-03-02");
+        assert_eq!(longest_streak(&connection, since).unwrap(), 2);
+    }
+}
@@ -0,0 +1,365 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Condvar, Mutex},
+    thread,
+};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use rusqlite::{Connection, OptionalExtension};
+
+use super::{
+    progress::ScanReporter,
+    scan::{worker_count, ScannedFile},
+    SongBase,
+};
+use crate::{error::SongBaseError, song::Song};
+
+/// Per-folder exclusions, one glob per line, relative to the folder.
+const IGNORE_FILE: &str = ".bzignore";
+
+/// What scans (and the library watcher) leave out. Stored in the library
+/// so every scan of it agrees.
+#[derive(Debug, PartialEq)]
+pub struct ScanOptions {
+    /// Globs matched against paths relative to the scanned folder. One
+    /// without a `/` matches a file or folder name at any depth.
+    pub exclusions: Vec<String>,
+    pub skip_hidden: bool,
+    /// How many folders deep a scan goes below the scanned one.
+    pub max_depth: Option<usize>,
+}
+
+impl ScanOptions {
+    const SKIP_HIDDEN: &'static str = "scan_skip_hidden";
+    const MAX_DEPTH: &'static str = "scan_max_depth";
+
+    pub(super) fn load(conn: &Connection) -> Result<Self, SongBaseError> {
+        let mut exclusion_query =
+            conn.prepare("SELECT pattern FROM scan_exclusions ORDER BY pattern")?;
+        let exclusions = exclusion_query
+            .query_map([], |row| row.get(0))?
+            .filter_map(|pattern| pattern.ok())
+            .collect();
+
+        let setting = |key: &str| -> Result<Option<String>, SongBaseError> {
+            Ok(conn
+                .query_row(
+                    "SELECT setting_value FROM settings WHERE setting_key = ?1",
+                    [key],
+                    |row| row.get(0),
+                )
+                .optional()?)
+        };
+
+        Ok(Self {
+            exclusions,
+            skip_hidden: setting(Self::SKIP_HIDDEN)?.map_or(true, |value| value == "1"),
+            max_depth: setting(Self::MAX_DEPTH)?.and_then(|value| value.parse().ok()),
+        })
+    }
+}
+
+impl SongBase {
+    pub fn get_scan_options(&self) -> Result<ScanOptions, SongBaseError> {
+        ScanOptions::load(&self.conn.lock().unwrap())
+    }
+
+    pub fn add_exclusion(&self, pattern: &str) -> Result<(), SongBaseError> {
+        GlobBuilder::new(pattern)
+            .build()
+            .map_err(|err| SongBaseError::InvalidPattern(err.to_string()))?;
+
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO scan_exclusions (pattern) VALUES (?1)",
+            [pattern],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_exclusion(&self, pattern: &str) -> Result<(), SongBaseError> {
+        let removed = self
+            .conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM scan_exclusions WHERE pattern = ?1", [pattern])?;
+        match removed {
+            0 => Err(SongBaseError::EntryNotFound),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn set_skip_hidden(&self, skip_hidden: bool) -> Result<(), SongBaseError> {
+        let value = if skip_hidden { "1" } else { "0" };
+        self.set_setting(ScanOptions::SKIP_HIDDEN, Some(value))
+    }
+
+    pub fn set_max_depth(&self, max_depth: Option<usize>) -> Result<(), SongBaseError> {
+        let value = max_depth.map(|depth| depth.to_string());
+        self.set_setting(ScanOptions::MAX_DEPTH, value.as_deref())
+    }
+
+    /// `None` removes the setting, bringing back its default.
+    pub(super) fn set_setting(&self, key: &str, value: Option<&str>) -> Result<(), SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        match value {
+            Some(value) => connection.execute(
+                "INSERT INTO settings (setting_key, setting_value) VALUES (?1, ?2)
+                ON CONFLICT (setting_key) DO UPDATE SET setting_value = excluded.setting_value",
+                [key, value],
+            )?,
+            None => connection.execute("DELETE FROM settings WHERE setting_key = ?1", [key])?,
+        };
+        Ok(())
+    }
+}
+
+/// Walks a scanned folder on several threads, leaving out what the
+/// `ScanOptions` and `.bzignore` files exclude and never visiting a folder
+/// twice, so symlink loops end.
+pub(super) struct Walker {
+    root: PathBuf,
+    exclusions: GlobSet,
+    skip_hidden: bool,
+    max_depth: Option<usize>,
+    visited: Mutex<HashSet<PathBuf>>,
+}
+
+/// A folder waiting to be read, with the `.bzignore` files above it.
+struct DirJob {
+    dir: PathBuf,
+    depth: usize,
+    ignores: Arc<Vec<(PathBuf, GlobSet)>>,
+}
+
+/// Folders left to read, and how many are being read right now. The walk is
+/// over once both run out.
+struct DirQueue {
+    state: Mutex<(Vec<DirJob>, usize)>,
+    changed: Condvar,
+}
+
+impl DirQueue {
+    fn push(&self, job: DirJob) {
+        self.state.lock().unwrap().0.push(job);
+        self.changed.notify_one();
+    }
+
+    /// The next folder to read, `None` when there's nothing left to do.
+    fn next(&self) -> Option<DirJob> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(job) = state.0.pop() {
+                state.1 += 1;
+                return Some(job);
+            }
+            if state.1 == 0 {
+                return None;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    fn done(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.1 -= 1;
+        if state.0.is_empty() && state.1 == 0 {
+            self.changed.notify_all();
+        }
+    }
+}
+
+impl Walker {
+    pub(super) fn new(root: &Path, options: &ScanOptions) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            exclusions: glob_set(options.exclusions.iter().map(String::as_str)),
+            skip_hidden: options.skip_hidden,
+            max_depth: options.max_depth,
+            visited: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Sends every song in `dir`, which has to be the root or inside it, as
+    /// it's found.
+    pub(super) fn walk(&self, dir: &Path, reporter: &ScanReporter, files: Sender<ScannedFile>) {
+        let mut ignores = Vec::new();
+
+        // Exclusions from .bzignore files above `dir` still apply in it
+        let depth = match dir.strip_prefix(&self.root) {
+            Ok(relative) => {
+                let mut parent = self.root.clone();
+                for component in relative.iter() {
+                    ignores.extend(read_ignore_file(&parent));
+                    parent.push(component);
+                }
+                relative.iter().count()
+            }
+            Err(_) => 0,
+        };
+
+        let queue = DirQueue {
+            state: Mutex::new((
+                vec![DirJob {
+                    dir: dir.to_path_buf(),
+                    depth,
+                    ignores: Arc::new(ignores),
+                }],
+                0,
+            )),
+            changed: Condvar::new(),
+        };
+        thread::scope(|scope| {
+            for _ in 0..worker_count() {
+                let files = files.clone();
+                let queue = &queue;
+                scope.spawn(move || {
+                    while let Some(job) = queue.next() {
+                        if !reporter.is_cancelled() {
+                            self.walk_dir(job, queue, reporter, &files);
+                        }
+                        queue.done();
+                    }
+                });
+            }
+        });
+    }
+
+    fn walk_dir(
+        &self,
+        job: DirJob,
+        queue: &DirQueue,
+        reporter: &ScanReporter,
+        files: &Sender<ScannedFile>,
+    ) {
+        let DirJob {
+            dir,
+            depth,
+            mut ignores,
+        } = job;
+        let Ok(real_dir) = dir.canonicalize() else {
+            reporter.error();
+            return;
+        };
+        if !self.visited.lock().unwrap().insert(real_dir) {
+            reporter.skipped();
+            return;
+        }
+
+        let Ok(read_dir) = dir.read_dir() else {
+            reporter.error();
+            return;
+        };
+        reporter.enter_dir(&dir);
+
+        if let Some(own_ignore) = read_ignore_file(&dir) {
+            let mut inherited = ignores.to_vec();
+            inherited.push(own_ignore);
+            ignores = Arc::new(inherited);
+        }
+
+        for entry in read_dir.flatten() {
+            if reporter.is_cancelled() {
+                break;
+            }
+            let entry_path = entry.path();
+
+            if entry_path.is_dir() {
+                if self.skips(&entry_path, true, depth + 1, &ignores) {
+                    reporter.skipped();
+                } else {
+                    queue.push(DirJob {
+                        dir: entry_path,
+                        depth: depth + 1,
+                        ignores: Arc::clone(&ignores),
+                    });
+                }
+            } else if Song::is_valid_song_path(&entry_path)
+                && !self.skips(&entry_path, false, depth + 1, &ignores)
+            {
+                match ScannedFile::of(&entry_path) {
+                    Some(file) => {
+                        reporter.file_seen();
+                        let _ = files.send(file);
+                    }
+                    None => reporter.error(),
+                }
+            }
+        }
+    }
+
+    /// Whether the watcher should leave `path` alone, checking each folder
+    /// on the way down from the root like a scan would.
+    pub(super) fn excludes(&self, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+
+        let components: Vec<_> = relative.iter().collect();
+        let mut ignores = Vec::new();
+        let mut current = self.root.clone();
+        for (index, component) in components.iter().enumerate() {
+            ignores.extend(read_ignore_file(&current));
+            current.push(component);
+
+            let is_dir = index + 1 < components.len() || current.is_dir();
+            if self.skips(&current, is_dir, index + 1, &ignores) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn skips(
+        &self,
+        path: &Path,
+        is_dir: bool,
+        depth: usize,
+        ignores: &[(PathBuf, GlobSet)],
+    ) -> bool {
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if is_dir && self.skip_hidden && hidden {
+            return true;
+        }
+        if is_dir && self.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            return true;
+        }
+
+        let excluded = path
+            .strip_prefix(&self.root)
+            .is_ok_and(|relative| self.exclusions.is_match(relative));
+        excluded
+            || ignores.iter().any(|(dir, ignore)| {
+                path.strip_prefix(dir)
+                    .is_ok_and(|relative| ignore.is_match(relative))
+            })
+    }
+}
+
+fn read_ignore_file(dir: &Path) -> Option<(PathBuf, GlobSet)> {
+    let contents = fs::read_to_string(dir.join(IGNORE_FILE)).ok()?;
+    Some((dir.to_path_buf(), glob_set(contents.lines())))
+}
+
+/// Comment lines start with `#`, invalid globs are left out.
+fn glob_set<'a>(patterns: impl Iterator<Item = &'a str>) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let pattern = pattern.trim().trim_end_matches('/');
+        if pattern.is_empty() || pattern.starts_with('#') {
+            continue;
+        }
+
+        let pattern = match pattern.contains('/') {
+            true => pattern.trim_start_matches('/').to_string(),
+            false => format!("**/{}", pattern),
+        };
+        if let Ok(glob) = GlobBuilder::new(&pattern).literal_separator(true).build() {
+            builder.add(glob);
+        }
+    }
+    builder.build().unwrap_or_else(|_| GlobSet::empty())
+}
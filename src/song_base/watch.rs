@@ -0,0 +1,258 @@
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf, MAIN_SEPARATOR},
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use notify::{
+    event::{AccessKind, AccessMode, ModifyKind, RenameMode},
+    Event, EventKind, RecursiveMode, Watcher,
+};
+use rusqlite::{params, Connection, ErrorCode};
+
+use super::{
+    progress::ScanReporter,
+    scan::ScannedFile,
+    walk::{ScanOptions, Walker},
+    SongBase,
+};
+use crate::{error::SongBaseError, player::PlayerAction, song::Song};
+
+/// Changes are applied once the folders have been quiet this long, so a
+/// file still being copied in is only read when it's complete.
+const SETTLE_TIME: Duration = Duration::from_secs(1);
+/// ... but never later than this after the first change.
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+impl SongBase {
+    /// Folders that were scanned into the library, oldest first.
+    pub fn get_roots(&self) -> Result<Vec<PathBuf>, SongBaseError> {
+        let connection = self.conn.lock().unwrap();
+        let mut root_query =
+            connection.prepare("SELECT root_path FROM library_roots ORDER BY root_id")?;
+
+        let roots = root_query
+            .query_map([], |row| row.get::<_, String>(0))?
+            .filter_map(|root| root.ok())
+            .map(PathBuf::from)
+            .collect();
+        Ok(roots)
+    }
+
+    /// Remembers a scanned folder and starts watching it when the library is
+    /// being watched.
+    pub(super) fn add_root(&self, root: &Path) -> Result<(), SongBaseError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR IGNORE INTO library_roots (root_path) VALUES (?1)",
+            [root.to_string_lossy()],
+        )?;
+
+        if let Some(watcher) = self.watcher.lock().unwrap().as_mut() {
+            watcher
+                .watch(root, RecursiveMode::Recursive)
+                .map_err(|err| SongBaseError::WatchFailed(err.to_string()))?;
+        }
+        Ok(())
+    }
+
+    /// Starts watching every library folder for new, changed, renamed and
+    /// deleted songs, returning how many folders are watched. Changes are
+    /// written to the library and reported over the sender.
+    pub fn watch_library(&self) -> Result<usize, SongBaseError> {
+        let (event_sender, event_receiver) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(event_sender)
+            .map_err(|err| SongBaseError::WatchFailed(err.to_string()))?;
+
+        let mut watched = 0;
+        for root in self.get_roots()? {
+            match watcher.watch(&root, RecursiveMode::Recursive) {
+                Ok(_) => watched += 1,
+                Err(err) => {
+                    let message = format!("Can't Watch {}: {}", root.display(), err);
+                    let _ = self.sender.send(PlayerAction::ConnectionMessage(message));
+                }
+            }
+        }
+
+        let connection = Arc::clone(&self.conn);
+        let sender = self.sender.clone();
+        // Ends once the watcher, and with it the event sender, is dropped
+        thread::spawn(move || Self::apply_events(event_receiver, &connection, &sender));
+
+        *self.watcher.lock().unwrap() = Some(watcher);
+        Ok(watched)
+    }
+
+    fn apply_events(
+        events: Receiver<notify::Result<Event>>,
+        conn: &Arc<Mutex<Connection>>,
+        sender: &Sender<PlayerAction>,
+    ) {
+        let mut pending = BTreeSet::new();
+        let mut first_change: Option<Instant> = None;
+
+        loop {
+            match events.recv_timeout(SETTLE_TIME) {
+                Ok(Ok(event)) => {
+                    match event.kind {
+                        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+                            if let [from, to] = &event.paths[..] {
+                                Self::rename_path(from, to, conn, sender);
+                            }
+                        }
+                        // Reads don't change anything, finished writes do
+                        EventKind::Access(AccessKind::Close(AccessMode::Write)) => (),
+                        EventKind::Access(_) => continue,
+                        _ => (),
+                    }
+                    pending.extend(event.paths);
+                    first_change.get_or_insert_with(Instant::now);
+
+                    if first_change.is_some_and(|first| first.elapsed() < MAX_DELAY) {
+                        continue;
+                    }
+                }
+                Ok(Err(err)) => {
+                    let message = format!("Library Watcher Error: {}", err);
+                    let _ = sender.send(PlayerAction::ConnectionMessage(message));
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => (),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+
+            // Sorted, so a folder comes right before everything inside it
+            let mut synced: Option<PathBuf> = None;
+            for path in std::mem::take(&mut pending) {
+                if synced
+                    .as_ref()
+                    .is_some_and(|folder| path.starts_with(folder))
+                {
+                    continue;
+                }
+                Self::sync_path(&path, conn, sender);
+                synced = Some(path);
+            }
+            first_change = None;
+        }
+    }
+
+    /// Catches the library up with whatever is at `path` now, be it a song,
+    /// a folder or nothing at all.
+    fn sync_path(path: &Path, conn: &Arc<Mutex<Connection>>, sender: &Sender<PlayerAction>) {
+        let walker = match Self::walker_for(path, conn) {
+            Ok(Some(walker)) => walker,
+            Ok(None) => return,
+            Err(err) => {
+                let message = format!("Can't Update {}: {}", display_name(path), err);
+                let _ = sender.send(PlayerAction::ConnectionMessage(message));
+                return;
+            }
+        };
+
+        let reporter = ScanReporter::silent();
+        let synced = if path.is_dir() {
+            Self::fetch_songs(&walker, path, conn, &reporter)
+        } else if Song::is_valid_song_path(path) || !path.exists() {
+            Self::sync_files(path, ScannedFile::of(path), conn, &reporter)
+        } else {
+            return;
+        };
+
+        let message = match synced {
+            Ok(summary) => {
+                let changes: Vec<String> = [
+                    (summary.added, "Added"),
+                    (summary.updated, "Updated"),
+                    (summary.missing, "Missing"),
+                ]
+                .iter()
+                .filter(|(count, _)| *count > 0)
+                .map(|(count, change)| format!("{} {}", count, change))
+                .collect();
+                if changes.is_empty() {
+                    return;
+                }
+                format!("{}: {}", display_name(path), changes.join(", "))
+            }
+            Err(err) => format!("Can't Update {}: {}", display_name(path), err),
+        };
+        let _ = sender.send(PlayerAction::ConnectionMessage(message));
+    }
+
+    /// A walker for the library folder `path` is in, `None` when the scan
+    /// options exclude the path.
+    fn walker_for(
+        path: &Path,
+        conn: &Arc<Mutex<Connection>>,
+    ) -> Result<Option<Walker>, SongBaseError> {
+        let connection = conn.lock().unwrap();
+        let options = ScanOptions::load(&connection)?;
+
+        let mut root_query = connection.prepare("SELECT root_path FROM library_roots")?;
+        let root = root_query
+            .query_map([], |row| row.get::<_, String>(0))?
+            .filter_map(|root| root.ok())
+            .map(PathBuf::from)
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+            .unwrap_or_else(|| path.to_path_buf());
+
+        let walker = Walker::new(&root, &options);
+        Ok((!walker.excludes(path)).then_some(walker))
+    }
+
+    /// Moves the songs at or under `from` to `to`, keeping their ids and so
+    /// their playlist links.
+    fn rename_path(
+        from: &Path,
+        to: &Path,
+        conn: &Arc<Mutex<Connection>>,
+        sender: &Sender<PlayerAction>,
+    ) {
+        if !to.is_dir() && !Song::is_valid_song_path(to) {
+            // Renamed away from audio, the sync afterwards marks it missing
+            return;
+        }
+
+        let song_name = to
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let renamed = conn.lock().unwrap().execute(
+            &format!(
+                "UPDATE songs SET song_path = ?3 || substr(song_path, length(?1) + 1),
+                    song_name = CASE WHEN song_path = ?1 THEN ?4 ELSE song_name END
+                WHERE {}",
+                Self::UNDER_PATH_SQL
+            ),
+            params![
+                from.to_string_lossy(),
+                MAIN_SEPARATOR.to_string(),
+                to.to_string_lossy(),
+                song_name,
+            ],
+        );
+
+        let message = match renamed {
+            Ok(0) => return,
+            Ok(_) => format!("Renamed {} -> {}", display_name(from), display_name(to)),
+            // Moved over a song that's already known, the syncs sort it out
+            Err(err) if err.sqlite_error_code() == Some(ErrorCode::ConstraintViolation) => return,
+            Err(err) => format!("Can't Rename {}: {}", display_name(from), err),
+        };
+        let _ = sender.send(PlayerAction::ConnectionMessage(message));
+    }
+}
+
+fn display_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or(path.as_os_str())
+        .to_string_lossy()
+        .to_string()
+}
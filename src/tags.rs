@@ -0,0 +1,223 @@
+use std::{fs::File, path::Path};
+
+use id3::{
+    frame::{ExtendedText, Popularimeter},
+    TagLike, Version,
+};
+use symphonia::core::{
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::Hint,
+};
+
+/// The POPM value written for 1 to 5 stars, as Windows Media Player and most
+/// taggers do.
+const POPM_STARS: [u8; 5] = [1, 64, 128, 196, 255];
+/// Free-form rating from 0.0 to 1.0, a TXXX frame in ID3 or a Vorbis comment.
+const FMPS_RATING: &str = "FMPS_Rating";
+
+/// Metadata embedded in an audio file (ID3v2, Vorbis comments, FLAC or MP4).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track_number: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    pub genre: Option<String>,
+    pub duration_ms: Option<u64>,
+    /// 1 to 5 stars, from a POPM or FMPS_Rating tag.
+    pub rating: Option<u8>,
+}
+
+impl Tags {
+    /// Reads whatever tags the file has; unreadable files just give no tags.
+    pub fn read(path: &Path) -> Self {
+        let mut tags = Self::default();
+        let Ok(file) = File::open(path) else {
+            return tags;
+        };
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let source = MediaSourceStream::new(Box::new(file), Default::default());
+        let Ok(mut probed) = symphonia::default::get_probe().format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        ) else {
+            return tags;
+        };
+
+        // Tags in front of the container (ID3v2 on mp3) come from the probe,
+        // the container's own ones win when both exist
+        if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            tags.apply(revision);
+        }
+        if let Some(revision) = probed.format.metadata().current() {
+            tags.apply(revision);
+        }
+
+        if let Some(track) = probed.format.default_track() {
+            let params = &track.codec_params;
+            if let (Some(frames), Some(sample_rate)) = (params.n_frames, params.sample_rate) {
+                tags.duration_ms = Some(frames * 1000 / sample_rate as u64);
+            }
+        }
+
+        tags
+    }
+
+    fn apply(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let value = tag.value.to_string().trim().to_string();
+            if value.is_empty() {
+                continue;
+            }
+            match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => self.title = Some(value),
+                Some(StandardTagKey::Artist) => self.artist = Some(value),
+                Some(StandardTagKey::Album) => self.album = Some(value),
+                Some(StandardTagKey::AlbumArtist) => self.album_artist = Some(value),
+                Some(StandardTagKey::Genre) => self.genre = Some(value),
+                Some(StandardTagKey::TrackNumber) => self.track_number = leading_number(&value),
+                Some(StandardTagKey::DiscNumber) => self.disc_number = leading_number(&value),
+                Some(StandardTagKey::Date)
+                | Some(StandardTagKey::ReleaseDate)
+                | Some(StandardTagKey::OriginalDate) => {
+                    self.year = self.year.or_else(|| leading_number(&value))
+                }
+                Some(StandardTagKey::Rating) if tag.key.starts_with("POPM") => {
+                    self.rating = value.parse().ok().and_then(popm_stars)
+                }
+                _ if is_fmps_rating(&tag.key) => self.rating = fmps_stars(&value),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// "3/12" -> 3, "1987-05-01" -> 1987
+fn leading_number(value: &str) -> Option<u32> {
+    let digits: String = value.chars().take_while(|ch| ch.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Writes `rating` into the file's ID3v2 tag as both POPM and FMPS_Rating,
+/// `None` takes them out. The tag keeps its version, a new one is v2.3 which
+/// more players read. Only mp3s are written, the other formats would need a
+/// tag writer of their own.
+pub fn write_rating(path: &Path, rating: Option<u8>) -> Result<(), String> {
+    let is_mp3 = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"));
+    if !is_mp3 {
+        return Err("Only mp3 Tags Are Written".to_string());
+    }
+
+    let mut tag = id3::no_tag_ok(id3::Tag::read_from_path(path))
+        .map_err(|err| err.to_string())?
+        .unwrap_or_else(|| id3::Tag::with_version(Version::Id3v23));
+    // v2.2 can't be written, its frames are upgraded to v2.3 on the way
+    let version = match tag.version() {
+        Version::Id3v24 => Version::Id3v24,
+        Version::Id3v22 | Version::Id3v23 => Version::Id3v23,
+    };
+    tag.remove("POPM");
+    tag.remove_extended_text(Some(FMPS_RATING), None);
+    if let Some(stars) = rating.filter(|stars| (1..=5).contains(stars)) {
+        tag.add_frame(Popularimeter {
+            user: String::new(),
+            rating: POPM_STARS[stars as usize - 1],
+            counter: 0,
+        });
+        tag.add_frame(ExtendedText {
+            description: FMPS_RATING.to_string(),
+            value: (stars as f32 / 5.0).to_string(),
+        });
+    }
+    tag.write_to_path(path, version)
+        .map_err(|err| err.to_string())
+}
+
+/// "TXXX:FMPS_Rating" in ID3, "FMPS_RATING" in Vorbis comments.
+fn is_fmps_rating(key: &str) -> bool {
+    key.rsplit(':')
+        .next()
+        .is_some_and(|name| name.eq_ignore_ascii_case(FMPS_RATING))
+}
+
+/// 0 is "not rated", the rest is split evenly between the stars.
+fn popm_stars(popm: u8) -> Option<u8> {
+    match popm {
+        0 => None,
+        1..=31 => Some(1),
+        32..=95 => Some(2),
+        96..=159 => Some(3),
+        160..=223 => Some(4),
+        _ => Some(5),
+    }
+}
+
+/// "0.8" -> 4 stars, 0 stars counts as not rated.
+fn fmps_stars(value: &str) -> Option<u8> {
+    let fraction: f32 = value.parse().ok()?;
+    let stars = (fraction.clamp(0.0, 1.0) * 5.0).round() as u8;
+    (stars > 0).then_some(stars)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    #[test]
+    fn popm_values_split_into_stars() {
+        assert_eq!(popm_stars(0), None);
+        let stars: Vec<Option<u8>> = POPM_STARS.iter().map(|&popm| popm_stars(popm)).collect();
+        assert_eq!(stars, [Some(1), Some(2), Some(3), Some(4), Some(5)]);
+        assert_eq!(popm_stars(31), Some(1));
+        assert_eq!(popm_stars(32), Some(2));
+        assert_eq!(popm_stars(224), Some(5));
+    }
+
+    #[test]
+    fn fmps_fractions_round_to_stars() {
+        assert_eq!(fmps_stars("0.8"), Some(4));
+        assert_eq!(fmps_stars("0.5"), Some(3));
+        assert_eq!(fmps_stars("1.7"), Some(5));
+        assert_eq!(fmps_stars("0"), None);
+        assert_eq!(fmps_stars("0.05"), None);
+        assert_eq!(fmps_stars("loved"), None);
+        assert!(is_fmps_rating("TXXX:FMPS_Rating"));
+        assert!(is_fmps_rating("FMPS_RATING"));
+    }
+
+    #[test]
+    fn write_rating_keeps_the_tag_version() {
+        let path = env::temp_dir().join(format!("bz_player_rating_{}.mp3", process::id()));
+        fs::write(&path, b"").unwrap();
+        let mut tag = id3::Tag::new();
+        tag.set_title("Kept");
+        tag.write_to_path(&path, Version::Id3v23).unwrap();
+
+        write_rating(&path, Some(4)).unwrap();
+        let tag = id3::Tag::read_from_path(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(tag.version(), Version::Id3v23);
+        assert_eq!(tag.title(), Some("Kept"));
+        let popm = tag
+            .frames()
+            .find_map(|frame| frame.content().popularimeter());
+        assert_eq!(popm.map(|popm| popm.rating), Some(POPM_STARS[3]));
+    }
+}
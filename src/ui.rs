@@ -1,8 +1,19 @@
 use crate::{
+    config::Config,
+    error::{PlayerError, SongBaseError},
+    m3u,
     player::{Player, PlayerAction},
-    song::{Playable, PlaylistActions},
-    song_base::SongBase,
-    utility::{render_playlist_view, render_search_song, UtilityState},
+    song::{
+        FilterActions, LabelActions, LibraryActions, Playable, Playlist, PlaylistActions,
+        RatingActions, ScanActions,
+    },
+    song_base::{DupeGroup, IntegrityReport, ScanJob, ScanProgress, SongBase, StatsPeriod},
+    utility::{
+        format_duration, format_rating, render_album, render_albums, render_artists, render_dupes,
+        render_filters, render_history, render_labels, render_library_check, render_playlist,
+        render_playlist_view, render_scan_options, render_search_song, render_stats, render_status,
+        render_utility_home, PlaylistView, UtilityData, UtilityState,
+    },
 };
 
 use crossterm::{
@@ -17,15 +28,30 @@ use ratatui::{
 };
 
 use std::{
+    fs,
     io::{self, stdout, Stdout},
-    path::PathBuf,
-    sync::mpsc::{self, Receiver},
+    panic,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender},
     time::Duration,
 };
 
 pub type Tui = Terminal<CrosstermBackend<Stdout>>;
 
+/// Plays listed by a bare `history`.
+const HISTORY_LENGTH: usize = 50;
+/// Where `export stats` writes to when not given a file, next to the library.
+const STATS_FILE: &str = "listening-stats.json";
+/// Unresolved entries listed after a playlist import, the rest are counted.
+const UNRESOLVED_SHOWN: usize = 10;
+
 pub fn init() -> io::Result<Tui> {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore();
+        default_hook(info);
+    }));
+
     execute!(stdout(), EnterAlternateScreen)?;
     enable_raw_mode()?;
     Terminal::new(CrosstermBackend::new(stdout()))
@@ -53,6 +79,30 @@ enum AppActions {
     Remove(usize),
     LogMessage(String),
     TogglePlayer,
+    Stop,
+    StopAfter,
+    Database(DatabaseActions),
+    Library(LibraryActions),
+    Filter(FilterActions),
+    Scan(ScanActions),
+    ExportStats(StatsPeriod, Option<PathBuf>),
+    Rating(RatingActions),
+    Label(LabelActions),
+    /// Goes ahead with what the last command asked about.
+    Confirm,
+}
+
+/// Commands that change a lot at once, run after a 'y'.
+enum Pending {
+    DeletePlaylist(u8),
+    RepairLibrary,
+}
+
+enum DatabaseActions {
+    Status,
+    Retry,
+    Open(PathBuf),
+    ImportLegacy,
 }
 
 impl AppActions {
@@ -67,7 +117,7 @@ impl AppActions {
             "add" | "push" => {
                 let args = command_splitted.get(1..);
                 match args {
-                    Some(args) if *args.first().unwrap() == "-p" => {
+                    Some(args) if args.first() == Some(&"-p") => {
                         if args.get(1).is_none() {
                             AppActions::Add(Playable::None)
                         } else {
@@ -80,7 +130,23 @@ impl AppActions {
                         }
                     }
 
-                    Some(args) if *args.first().unwrap() == "-i" => {
+                    Some(args) if args.first() == Some(&"-a") => {
+                        match args.get(1).and_then(|id| id.parse::<u32>().ok()) {
+                            Some(album_id) => AppActions::Add(Playable::Album(album_id)),
+                            None => AppActions::LogMessage("usage: add -a <album id>".to_string()),
+                        }
+                    }
+
+                    Some(args) if args.first() == Some(&"-l") => match args.get(1) {
+                        Some(label) => AppActions::Add(Playable::Label(label.to_string())),
+                        None => AppActions::LogMessage("usage: add -l <label>".to_string()),
+                    },
+
+                    Some(args) if args.first() == Some(&"-f") => {
+                        AppActions::Add(Playable::Favorites)
+                    }
+
+                    Some(args) if args.first() == Some(&"-i") => {
                         if args.get(1).is_none() {
                             AppActions::Add(Playable::None)
                         } else {
@@ -95,7 +161,7 @@ impl AppActions {
                     }
 
                     Some(song_name) => {
-                        if song_name.first().is_none() {
+                        if song_name.is_empty() {
                             AppActions::Add(Playable::None)
                         } else {
                             let mut current_song_name = String::new();
@@ -125,14 +191,34 @@ impl AppActions {
             "play" | "res" | "resume" => AppActions::Play,
             "next" | "skip" => AppActions::NextSong,
             "pause" | "wait" => AppActions::Pause,
-            "fetch" | "scan" => {
-                let path = command_splitted.get(1);
-                if let Some(path) = path {
-                    AppActions::Fetch(Some(path.to_owned().to_string()))
-                } else {
-                    AppActions::Fetch(None)
+            "stop" => AppActions::Stop,
+            "stop-after" | "sa" => AppActions::StopAfter,
+            "fetch" | "scan" => match command_splitted.get(1) {
+                Some(&"exclude") if command_splitted.len() > 2 => {
+                    AppActions::Scan(ScanActions::Exclude(command_splitted[2..].join(" ")))
                 }
-            }
+                Some(&"include") if command_splitted.len() > 2 => {
+                    AppActions::Scan(ScanActions::Include(command_splitted[2..].join(" ")))
+                }
+                Some(&"depth") => match command_splitted.get(2) {
+                    Some(&"off") => AppActions::Scan(ScanActions::Depth(None)),
+                    Some(depth) => match depth.parse::<usize>() {
+                        Ok(depth) => AppActions::Scan(ScanActions::Depth(Some(depth))),
+                        Err(_) => AppActions::Scan(ScanActions::Invalid),
+                    },
+                    None => AppActions::Scan(ScanActions::Invalid),
+                },
+                Some(&"hidden") => match command_splitted.get(2) {
+                    Some(&"on") => AppActions::Scan(ScanActions::Hidden(true)),
+                    Some(&"off") => AppActions::Scan(ScanActions::Hidden(false)),
+                    _ => AppActions::Scan(ScanActions::Invalid),
+                },
+                Some(&"exclude") | Some(&"include") => AppActions::Scan(ScanActions::Invalid),
+                Some(&"settings") => AppActions::Utility(UtilityState::ScanOptions),
+                Some(&"cancel") => AppActions::Scan(ScanActions::Cancel),
+                Some(path) => AppActions::Fetch(Some(path.to_string())),
+                None => AppActions::Fetch(None),
+            },
             "prev" | "back" | "rollback" => AppActions::PrevSong,
             "jump" => {
                 let song_index = command_splitted.get(1);
@@ -159,22 +245,57 @@ impl AppActions {
                 }
             }
             "exit" | "quit" | "out" => AppActions::Exit,
-            "playlist" => {
-                let args = command_splitted.get(1..);
-                if args.is_none() {
-                    return AppActions::Utility(UtilityState::Playlist(PlaylistActions::Show));
+            "y" | "yes" => AppActions::Confirm,
+            "library" | "lib" => match command_splitted.get(1) {
+                Some(&"check") => AppActions::Library(LibraryActions::Check(
+                    command_splitted[2..].contains(&"--fix"),
+                )),
+                Some(&"dupes") => AppActions::Library(LibraryActions::Dupes),
+                Some(&"merge") => {
+                    let group = command_splitted.get(2).map(|group| group.parse::<usize>());
+                    let keep = command_splitted.get(3).map(|keep| keep.parse::<usize>());
+                    match (group, keep) {
+                        (Some(Ok(group)), None) => {
+                            AppActions::Library(LibraryActions::Merge(group, None))
+                        }
+                        (Some(Ok(group)), Some(Ok(keep))) => {
+                            AppActions::Library(LibraryActions::Merge(group, Some(keep)))
+                        }
+                        _ => AppActions::Library(LibraryActions::Invalid),
+                    }
                 }
-                let args = args.unwrap();
-                let playlist_subcommand = args.get(0);
-                if playlist_subcommand.is_none() {
+                _ => AppActions::Library(LibraryActions::Invalid),
+            },
+            "db" | "database" => match command_splitted.get(1) {
+                None | Some(&"status") => AppActions::Database(DatabaseActions::Status),
+                Some(&"retry") => AppActions::Database(DatabaseActions::Retry),
+                Some(&"import") => AppActions::Database(DatabaseActions::ImportLegacy),
+                Some(&"open") if command_splitted.len() > 2 => AppActions::Database(
+                    DatabaseActions::Open(PathBuf::from(command_splitted[2..].join(" "))),
+                ),
+                Some(&"open") => AppActions::LogMessage("usage: db open <path>".to_string()),
+                Some(_) => AppActions::Invalid,
+            },
+            "playlist" => {
+                let args = &command_splitted[1..];
+                let Some(playlist_subcommand) = args.first() else {
                     return AppActions::Utility(UtilityState::Playlist(PlaylistActions::Show));
-                }
-                let playlist_command = match *playlist_subcommand.unwrap() {
+                };
+                let playlist_command = match *playlist_subcommand {
                     "show" | "-s" | "s" => PlaylistActions::Show,
                     "create" | "-c" | "c" => match args.get(1..) {
                         None => PlaylistActions::Create(None, None),
                         Some(playlist_create_args) => {
-                            if let Some(folder_index) =
+                            if let Some(rule_index) =
+                                playlist_create_args.iter().position(|arg| arg == &"-r")
+                            {
+                                let playlist_name = playlist_create_args[..rule_index].join(" ");
+                                let rule = playlist_create_args[(rule_index + 1)..].join(" ");
+                                match playlist_name.is_empty() || rule.is_empty() {
+                                    true => PlaylistActions::Invalid,
+                                    false => PlaylistActions::CreateSmart(playlist_name, rule),
+                                }
+                            } else if let Some(folder_index) =
                                 playlist_create_args.iter().position(|arg| arg == &"-f")
                             {
                                 let playlist_name = playlist_create_args[..folder_index].join(" ");
@@ -192,59 +313,180 @@ impl AppActions {
                         }
                     },
                     "add" | "-a" | "a" => {
-                        let playlist_args = args.get(1..);
-                        if playlist_args.is_none() {
-                            PlaylistActions::Add(0, None)
-                        } else {
-                            let args = playlist_args.unwrap();
-                            let playlist_id = args.first().unwrap().parse::<u8>();
-                            if playlist_id.is_err() {
-                                PlaylistActions::Add(0, None)
-                            } else {
-                                let song_names = args.get(1..);
-                                if song_names.is_none() {
-                                    PlaylistActions::Add(playlist_id.unwrap(), None)
-                                } else {
-                                    let is_whole = song_names.unwrap().first();
-                                    if is_whole.is_none() {
-                                        PlaylistActions::Add(0, None)
-                                    } else if is_whole.unwrap().eq(&"*") {
-                                        PlaylistActions::AddAll(playlist_id.ok())
-                                    } else {
-                                        let song_names: Vec<String> = song_names
-                                            .unwrap()
-                                            .join(" ")
-                                            .split(",")
-                                            .map(|s| s.trim().to_owned())
-                                            .collect();
-                                        PlaylistActions::Add(playlist_id.unwrap(), Some(song_names))
-                                    }
-                                }
+                        let playlist_id = args.get(1).and_then(|id| id.parse::<u8>().ok());
+                        let song_names = args.get(2..).unwrap_or_default();
+                        match (playlist_id, song_names.first()) {
+                            (None, _) => PlaylistActions::Add(0, None),
+                            (Some(playlist_id), None) => PlaylistActions::Add(playlist_id, None),
+                            (Some(playlist_id), Some(&"*")) => {
+                                PlaylistActions::AddAll(Some(playlist_id))
+                            }
+                            (Some(playlist_id), Some(_)) => {
+                                let song_names: Vec<String> = song_names
+                                    .join(" ")
+                                    .split(',')
+                                    .map(|s| s.trim().to_owned())
+                                    .collect();
+                                PlaylistActions::Add(playlist_id, Some(song_names))
                             }
                         }
                     }
                     "view" | "-v" | "v" => {
-                        let playlist_id = args.get(1);
-                        if playlist_id.is_none() {
-                            PlaylistActions::View(None)
-                        } else {
-                            let playlist_id = playlist_id.unwrap().parse::<u8>().ok();
-                            PlaylistActions::View(playlist_id)
+                        PlaylistActions::View(args.get(1).and_then(|id| id.parse::<u8>().ok()))
+                    }
+                    "import" => match args.get(1..) {
+                        Some(path) if !path.is_empty() => {
+                            PlaylistActions::Import(PathBuf::from(path.join(" ")))
+                        }
+                        _ => PlaylistActions::Invalid,
+                    },
+                    subcommand => {
+                        let playlist_id = args.get(1).and_then(|id| id.parse::<u8>().ok());
+                        let rest = args.get(2..).unwrap_or_default();
+                        let positions: Option<Vec<usize>> = rest
+                            .join(" ")
+                            .split([',', ' '])
+                            .filter(|position| !position.is_empty())
+                            .map(|position| position.parse().ok())
+                            .collect();
+                        match (subcommand, playlist_id) {
+                            (_, None) => PlaylistActions::Invalid,
+                            ("remove" | "rm", Some(id)) => match positions {
+                                Some(positions) if !positions.is_empty() => {
+                                    PlaylistActions::Remove(id, positions)
+                                }
+                                _ => PlaylistActions::Invalid,
+                            },
+                            ("move" | "mv", Some(id)) => match positions.as_deref() {
+                                Some(&[from, to]) => PlaylistActions::Move(id, from, to),
+                                _ => PlaylistActions::Invalid,
+                            },
+                            ("rename", Some(id)) if !rest.is_empty() => {
+                                PlaylistActions::Rename(id, rest.join(" "))
+                            }
+                            ("delete" | "del", Some(id)) => PlaylistActions::Delete(id),
+                            ("export", Some(id)) if !rest.is_empty() => {
+                                PlaylistActions::Export(id, PathBuf::from(rest.join(" ")))
+                            }
+                            ("dup" | "copy", Some(id)) => PlaylistActions::Duplicate(
+                                id,
+                                (!rest.is_empty()).then(|| rest.join(" ")),
+                            ),
+                            _ => PlaylistActions::Invalid,
                         }
                     }
-                    _ => PlaylistActions::Invalid,
                 };
                 AppActions::Utility(UtilityState::Playlist(playlist_command))
             }
-            "search" => {
-                let song_name = command_splitted.get(1..);
-                match song_name.is_none() {
-                    true => AppActions::Utility(UtilityState::SearchSong("*".to_string())),
-                    false => {
-                        AppActions::Utility(UtilityState::SearchSong(song_name.unwrap().join(" ")))
+            "artists" => AppActions::Utility(UtilityState::Artists),
+            "albums" => AppActions::Utility(UtilityState::Albums(
+                (command_splitted.len() > 1).then(|| command_splitted[1..].join(" ")),
+            )),
+            "album" => match command_splitted
+                .get(1)
+                .and_then(|id| id.parse::<u32>().ok())
+            {
+                Some(album_id) => AppActions::Utility(UtilityState::Album(album_id)),
+                None => AppActions::LogMessage("usage: album <id>".to_string()),
+            },
+            "search" => match command_splitted.len() {
+                1 => AppActions::Utility(UtilityState::SearchSong("*".to_string())),
+                _ => AppActions::Utility(UtilityState::SearchSong(command_splitted[1..].join(" "))),
+            },
+            "filters" => AppActions::Utility(UtilityState::Filters),
+            "history" => match command_splitted.get(1).map(|limit| limit.parse::<usize>()) {
+                None => AppActions::Utility(UtilityState::History(HISTORY_LENGTH)),
+                Some(Ok(limit)) => AppActions::Utility(UtilityState::History(limit)),
+                Some(Err(_)) => AppActions::LogMessage("usage: history [count]".to_string()),
+            },
+            "rate" => {
+                let song_name =
+                    (command_splitted.len() > 2).then(|| command_splitted[2..].join(" "));
+                let rating_command = match command_splitted.get(1) {
+                    Some(&"tags") => match command_splitted.get(2) {
+                        Some(&"on") => RatingActions::WriteTags(true),
+                        Some(&"off") => RatingActions::WriteTags(false),
+                        _ => RatingActions::Invalid,
+                    },
+                    Some(stars) => match stars.parse::<u8>() {
+                        Ok(0) => RatingActions::Rate(None, song_name),
+                        Ok(stars) if stars <= 5 => RatingActions::Rate(Some(stars), song_name),
+                        _ => RatingActions::Invalid,
+                    },
+                    None => RatingActions::Invalid,
+                };
+                AppActions::Rating(rating_command)
+            }
+            "labels" => AppActions::Utility(UtilityState::Labels),
+            "label" => {
+                let song_names = (command_splitted.len() > 3).then(|| {
+                    command_splitted[3..]
+                        .join(" ")
+                        .split(',')
+                        .map(|name| name.trim().to_string())
+                        .filter(|name| !name.is_empty())
+                        .collect::<Vec<String>>()
+                });
+                let label_command = match (command_splitted.get(1), command_splitted.get(2)) {
+                    (Some(&"add"), Some(label)) => match song_names {
+                        Some(song_names) if song_names != ["*"] => {
+                            LabelActions::Add(label.to_string(), Some(song_names))
+                        }
+                        Some(_) => LabelActions::Add(label.to_string(), None),
+                        None => LabelActions::Invalid,
+                    },
+                    (Some(&"rm") | Some(&"remove"), Some(label)) => {
+                        LabelActions::Remove(label.to_string(), song_names)
                     }
-                }
+                    _ => LabelActions::Invalid,
+                };
+                AppActions::Label(label_command)
             }
+            "fav" | "unfav" => AppActions::Rating(RatingActions::Favorite(
+                main_command == "fav",
+                (command_splitted.len() > 1).then(|| command_splitted[1..].join(" ")),
+            )),
+            "stats" => match command_splitted
+                .get(1)
+                .map(|period| StatsPeriod::parse(period))
+            {
+                None => AppActions::Utility(UtilityState::Stats(StatsPeriod::All)),
+                Some(Some(period)) => AppActions::Utility(UtilityState::Stats(period)),
+                Some(None) => {
+                    AppActions::LogMessage("usage: stats [week|month|year|all]".to_string())
+                }
+            },
+            "export" => match (command_splitted.get(1), command_splitted.get(2)) {
+                (Some(&"stats"), None) => AppActions::ExportStats(StatsPeriod::All, None),
+                (Some(&"stats"), Some(period)) => match StatsPeriod::parse(period) {
+                    Some(period) => AppActions::ExportStats(
+                        period,
+                        (command_splitted.len() > 3)
+                            .then(|| PathBuf::from(command_splitted[3..].join(" "))),
+                    ),
+                    None => AppActions::LogMessage(
+                        "usage: export stats [week|month|year|all] [file]".to_string(),
+                    ),
+                },
+                _ => AppActions::LogMessage(
+                    "usage: export stats [week|month|year|all] [file]".to_string(),
+                ),
+            },
+            "filter" => match command_splitted.get(1) {
+                None | Some(&"list") => AppActions::Utility(UtilityState::Filters),
+                Some(&"save") => match command_splitted.get(2) {
+                    Some(name) => AppActions::Filter(FilterActions::Save(
+                        name.to_string(),
+                        (command_splitted.len() > 3).then(|| command_splitted[3..].join(" ")),
+                    )),
+                    None => AppActions::Filter(FilterActions::Invalid),
+                },
+                Some(&"rm") | Some(&"remove") => match command_splitted.get(2) {
+                    Some(name) => AppActions::Filter(FilterActions::Remove(name.to_string())),
+                    None => AppActions::Filter(FilterActions::Invalid),
+                },
+                Some(_) => AppActions::Filter(FilterActions::Invalid),
+            },
             _ => AppActions::Invalid,
         }
     }
@@ -256,44 +498,540 @@ pub struct App {
     info: Vec<String>,
     info_lines: u32,
     player: Player,
+    sender: Sender<PlayerAction>,
     receiver: Receiver<PlayerAction>,
     song_base: SongBase,
+    db_path: PathBuf,
+    legacy_db: Option<PathBuf>,
+    db_error: Option<String>,
+    audio_error: Option<String>,
+    library_report: Option<IntegrityReport>,
     utility_state: UtilityState,
+    scan_job: Option<ScanJob>,
+    scan_progress: Option<ScanProgress>,
+    dupe_groups: Option<Vec<DupeGroup>>,
+    /// What `utility_state` shows, see `load_utility`.
+    utility_data: Option<UtilityData>,
+    /// Loaded once by `playlist view`, so a shuffled rule stays put.
+    viewed_playlist: Option<PlaylistView>,
+    /// What's waiting for a 'y' before it goes ahead.
+    pending: Option<Pending>,
 }
 
 impl App {
-    pub fn new() -> App {
+    pub fn new(config: Config) -> App {
         let (sender, receiver) = mpsc::channel();
-        let sender_clone = sender.clone();
-        let player = Player::new(sender);
 
-        let song_base = SongBase::init("song.db", sender_clone).unwrap();
+        let (player, audio_error) = match Player::new(sender.clone()) {
+            Ok(player) => (player, None),
+            Err(err) => (Player::new_idle(sender.clone()), Some(err.to_string())),
+        };
+
+        let Config { db_path, legacy_db } = config;
+        let (song_base, db_error) = match SongBase::init(&db_path, sender.clone()) {
+            Ok(song_base) => (song_base, None),
+            Err(err) => (
+                SongBase::init(SongBase::IN_MEMORY, sender.clone())
+                    .expect("in-memory database should always open"),
+                Some(err.to_string()),
+            ),
+        };
+
+        let utility_state = if db_error.is_some() || audio_error.is_some() {
+            UtilityState::Status
+        } else {
+            UtilityState::Help
+        };
+
         App {
             exit: false,
             command: String::new(),
             info: Vec::new(),
             info_lines: 1,
             player,
+            sender,
             receiver,
             song_base,
-            utility_state: UtilityState::Help,
+            db_path,
+            legacy_db,
+            db_error,
+            audio_error,
+            library_report: None,
+            utility_state,
+            scan_job: None,
+            scan_progress: None,
+            dupe_groups: None,
+            utility_data: None,
+            viewed_playlist: None,
+            pending: None,
         }
     }
 
+    fn open_database(&mut self, db_path: PathBuf) {
+        match SongBase::init(&db_path, self.sender.clone()) {
+            Ok(song_base) => {
+                self.song_base = song_base;
+                self.log_info(format!("Library Opened @ {}", db_path.display()));
+                self.db_path = db_path;
+                self.db_error = None;
+                self.watch_library();
+                self.load_utility();
+            }
+            Err(err) => self.log_info(format!("Can't Open {}: {}", db_path.display(), err)),
+        }
+    }
+
+    /// Keeps one copy of the `group`th duplicate group (1 based, as shown)
+    /// and hides the rest behind it.
+    fn merge_dupes(&mut self, group: usize, keep: Option<usize>) {
+        let Some(groups) = self.dupe_groups.as_mut() else {
+            self.log_info("Run 'library dupes' First");
+            return;
+        };
+        let Some(dupe_group) = group.checked_sub(1).and_then(|index| groups.get(index)) else {
+            self.log_info(format!("No Duplicate Group {}", group));
+            return;
+        };
+        let keep_index = keep.unwrap_or(1);
+        let Some(kept) = keep_index
+            .checked_sub(1)
+            .and_then(|index| dupe_group.songs.get(index))
+        else {
+            self.log_info(format!("Group {} Has No Copy {}", group, keep_index));
+            return;
+        };
+
+        let duplicates: Vec<u32> = dupe_group.songs.iter().map(|song| song.song_id).collect();
+        let message = match self.song_base.merge_dupes(kept.song_id, &duplicates) {
+            Ok(moved) => {
+                let message = format!(
+                    "Kept {}, {} Playlist Links Moved to It",
+                    kept.song_path, moved
+                );
+                groups.remove(group - 1);
+                message
+            }
+            Err(err) => err.to_string(),
+        };
+        self.log_info(message);
+    }
+
+    /// Loads what the utility pane shows for its state, so drawing doesn't
+    /// query the library on every frame.
+    fn load_utility(&mut self) {
+        let song_base = &self.song_base;
+        self.utility_data = match &self.utility_state {
+            UtilityState::SearchSong(song_name) => {
+                Some(UtilityData::Search(song_base.search(song_name)))
+            }
+            UtilityState::History(limit) => {
+                Some(UtilityData::History(song_base.get_history(*limit)))
+            }
+            UtilityState::Labels => Some(UtilityData::Labels(song_base.get_labels())),
+            UtilityState::Artists => Some(UtilityData::Artists(song_base.get_artists())),
+            UtilityState::Albums(artist) => {
+                Some(UtilityData::Albums(song_base.get_albums(artist.as_deref())))
+            }
+            UtilityState::Album(album_id) => {
+                Some(UtilityData::Album(song_base.get_album(*album_id)))
+            }
+            UtilityState::Stats(period) => {
+                Some(UtilityData::Stats(Box::new(song_base.get_stats(*period))))
+            }
+            _ => None,
+        };
+    }
+
+    /// The name of the playlist with that id, `None` when there is none.
+    fn playlist_name(&self, playlist_id: u8) -> Option<String> {
+        self.song_base
+            .get_playlists()
+            .ok()?
+            .into_iter()
+            .find(|(id, _, _)| *id == playlist_id)
+            .map(|(_, playlist_name, _)| playlist_name)
+    }
+
+    /// Loads the viewed playlist again after it was changed.
+    fn refresh_viewed_playlist(&mut self, playlist_id: u8) {
+        if self.utility_state != UtilityState::Playlist(PlaylistActions::View(Some(playlist_id))) {
+            return;
+        }
+        match (
+            self.song_base.get_playlist(playlist_id),
+            &mut self.viewed_playlist,
+        ) {
+            (Ok(playlist), Some(view)) => view.reload(playlist),
+            (Ok(playlist), None) => self.viewed_playlist = Some(PlaylistView::new(playlist)),
+            (Err(err), _) => self.log_info(err),
+        }
+    }
+
+    /// Queues the picked entries of the viewed playlist, or all of it when
+    /// none are picked.
+    fn queue_viewed_playlist(&mut self) {
+        let Some(view) = self.viewed_playlist.as_mut() else {
+            return;
+        };
+        let song_ids = view.queued_ids();
+        view.selected.clear();
+
+        let mut songs = Playlist::new(&view.playlist.playlist_name);
+        for song_id in song_ids {
+            if let Ok(song) = self.song_base.find_song_by_id(song_id) {
+                songs.add_song(song);
+            }
+        }
+        let (queued, playlist_name) = (songs.songs.len(), songs.playlist_name.clone());
+        match self.player.add_playlist(songs) {
+            Ok(_) => self.log_info(format!("Queued {} Songs from {}", queued, playlist_name)),
+            Err(err) => self.log_info(err),
+        }
+    }
+
+    fn rate(&mut self, rating_action: RatingActions) {
+        match rating_action {
+            RatingActions::Rate(rating, song_name) => {
+                let Some((song_id, display_name)) = self.target_song(song_name) else {
+                    return;
+                };
+                let rated = self.song_base.set_rating(song_id, rating);
+                if matches!(rated, Ok(_) | Err(SongBaseError::RatingNotWritten(_))) {
+                    self.player
+                        .update_queued(song_id, |song| song.tags.rating = rating);
+                }
+                match (rated, rating) {
+                    (Ok(_), Some(_)) => self.log_info(format!(
+                        "Rated {} {}",
+                        display_name,
+                        format_rating(rating, false)
+                    )),
+                    (Ok(_), None) => self.log_info(format!("Cleared Rating of {}", display_name)),
+                    (Err(err), _) => self.log_info(err),
+                }
+            }
+            RatingActions::Favorite(favorite, song_name) => {
+                let Some((song_id, display_name)) = self.target_song(song_name) else {
+                    return;
+                };
+                match self.song_base.set_favorite(song_id, favorite) {
+                    Ok(_) => {
+                        self.player
+                            .update_queued(song_id, |song| song.favorite = favorite);
+                        self.log_info(match favorite {
+                            true => format!("Added {} to Favorites", display_name),
+                            false => format!("Removed {} from Favorites", display_name),
+                        });
+                    }
+                    Err(err) => self.log_info(err),
+                }
+            }
+            RatingActions::WriteTags(write) => match self.song_base.set_write_ratings(write) {
+                Ok(_) if write => self.log_info("Ratings Will Be Written to mp3 Tags Too"),
+                Ok(_) => self.log_info("Ratings Stay in the Library Only"),
+                Err(err) => self.log_info(err),
+            },
+            RatingActions::Invalid => self
+                .log_info("usage: rate <1-5> [song] / rate 0 [song] clears / rate tags <on|off>"),
+        }
+    }
+
+    fn label(&mut self, label_action: LabelActions) {
+        match label_action {
+            LabelActions::Add(label, song_names) => {
+                let song_ids = match song_names {
+                    Some(song_names) => self.find_songs(song_names),
+                    None => self.player.get_queue_ids(),
+                };
+                match self.song_base.add_label(&label, &song_ids) {
+                    Ok(added) => self.log_info(format!("Labelled {} Songs {}", added, label)),
+                    Err(err) => self.log_info(err),
+                }
+            }
+            LabelActions::Remove(label, song_names) => {
+                let song_ids = song_names.map(|song_names| self.find_songs(song_names));
+                match self.song_base.remove_label(&label, song_ids.as_deref()) {
+                    Ok(removed) if song_ids.is_some() => {
+                        self.log_info(format!("Took {} Off {} Songs", label, removed))
+                    }
+                    Ok(removed) => {
+                        self.log_info(format!("Removed Label {} From {} Songs", label, removed))
+                    }
+                    Err(err) => self.log_info(err),
+                }
+            }
+            LabelActions::Invalid => self.log_info(
+                "usage: label add <label> <song, song..|*> / label rm <label> [song, song..]",
+            ),
+        }
+        if self.utility_state == UtilityState::Labels {
+            self.load_utility();
+        }
+    }
+
+    /// Ids of the best match for each name, logging the ones not found.
+    fn find_songs(&mut self, song_names: Vec<String>) -> Vec<u32> {
+        let mut song_ids = Vec::new();
+        for song_name in song_names {
+            match self.song_base.find_song_by_name(song_name) {
+                Ok(song) => song_ids.push(song.song_id),
+                Err(err) => self.log_info(err),
+            }
+        }
+        song_ids
+    }
+
+    /// The named song, or the one playing when there's no name.
+    fn target_song(&mut self, song_name: Option<String>) -> Option<(u32, String)> {
+        let song = match song_name {
+            Some(song_name) => self
+                .song_base
+                .find_song_by_name(song_name)
+                .map(|song| (song.song_id, song.display_name()))
+                .map_err(|err| err.to_string()),
+            None => self
+                .player
+                .current_song_id()
+                .map(|song_id| (song_id, self.player.current_song_name()))
+                .ok_or_else(|| "Nothing Playing, Name a Song".to_string()),
+        };
+        match song {
+            Ok(song) => Some(song),
+            Err(err) => {
+                self.log_info(err);
+                None
+            }
+        }
+    }
+
+    /// Writes the listening stats for `period` out as JSON.
+    fn export_stats(&mut self, period: StatsPeriod, path: Option<PathBuf>) {
+        let stats = match self.song_base.get_stats(period) {
+            Ok(stats) => stats,
+            Err(err) => return self.log_info(err),
+        };
+        let path = path.unwrap_or_else(|| self.db_path.with_file_name(STATS_FILE));
+
+        let written = serde_json::to_string_pretty(&stats)
+            .map_err(io::Error::from)
+            .and_then(|json| fs::write(&path, json));
+        match written {
+            Ok(_) => self.log_info(format!("Stats Exported @ {}", path.display())),
+            Err(err) => self.log_info(format!("Can't Export Stats: {}", err)),
+        }
+    }
+
+    fn import_playlist(&mut self, path: &Path) {
+        let import = match self.song_base.import_playlist(path) {
+            Ok(import) => import,
+            Err(err) => return self.log_info(err),
+        };
+        self.log_info(&import);
+        for entry in import.unresolved.iter().take(UNRESOLVED_SHOWN) {
+            self.log_info(format!("Unresolved: {}", entry));
+        }
+        if import.unresolved.len() > UNRESOLVED_SHOWN {
+            self.log_info(format!(
+                "And {} More Unresolved",
+                import.unresolved.len() - UNRESOLVED_SHOWN
+            ));
+        }
+    }
+
+    /// Writes the playlist out as an M3U file, a smart one as it is now.
+    fn export_playlist(&mut self, playlist_id: u8, path: &Path) {
+        let playlist = match self.song_base.get_playlist(playlist_id) {
+            Ok(playlist) => playlist,
+            Err(err) => return self.log_info(err),
+        };
+        match fs::write(path, m3u::write(&playlist)) {
+            Ok(_) => self.log_info(format!(
+                "Exported {} Songs @ {}",
+                playlist.songs.len(),
+                path.display()
+            )),
+            Err(err) => self.log_info(format!("Can't Export the Playlist: {}", err)),
+        }
+    }
+
+    fn delete_playlist(&mut self, playlist_id: u8) {
+        match self.song_base.delete_playlist(playlist_id) {
+            Ok(()) => {
+                self.log_info(format!("Deleted Playlist {}", playlist_id));
+                if self.utility_state
+                    == UtilityState::Playlist(PlaylistActions::View(Some(playlist_id)))
+                {
+                    self.viewed_playlist = None;
+                    self.utility_state = UtilityState::Playlist(PlaylistActions::Show);
+                }
+            }
+            Err(err) => self.log_info(err),
+        }
+    }
+
+    fn check_library(&mut self, repair: bool) {
+        match self.song_base.check_library(repair) {
+            Ok(report) => {
+                self.log_info(report.summary());
+                self.library_report = Some(report);
+                self.utility_state = UtilityState::LibraryCheck;
+            }
+            Err(err) => {
+                self.library_report = None;
+                self.log_info(err);
+            }
+        }
+    }
+
+    fn watch_library(&mut self) {
+        match self.song_base.watch_library() {
+            Ok(0) => (),
+            Ok(watched) => self.log_info(format!("Watching {} Library Folders", watched)),
+            Err(err) => self.log_info(err),
+        }
+    }
+
+    /// Moves an old `./song.db` into the library location, once.
+    fn import_legacy_db(&mut self) {
+        let Some(legacy_db) = self.legacy_db.clone() else {
+            self.log_info("No Old Library to Import");
+            return;
+        };
+
+        if self.db_error.is_none() && !self.song_base.is_empty().unwrap_or(false) {
+            self.log_info(format!(
+                "Library @ {} isn't Empty, Not Replacing It",
+                self.db_path.display()
+            ));
+            return;
+        }
+
+        // A library that failed to open may still be a real one (newer or
+        // locked), so whatever is there is kept aside, never overwritten
+        let mut backup = self.db_path.clone().into_os_string();
+        backup.push(".bak");
+        let backup = PathBuf::from(backup);
+        if self.db_path.exists() && backup.exists() {
+            self.log_info(format!(
+                "{} Already Exists, Not Replacing the Library",
+                backup.display()
+            ));
+            return;
+        }
+
+        // Let go of the current file before it gets moved
+        self.song_base = SongBase::init(SongBase::IN_MEMORY, self.sender.clone())
+            .expect("in-memory database should always open");
+
+        let backed_up = self.db_path.exists();
+        if backed_up {
+            if let Err(err) = fs::rename(&self.db_path, &backup) {
+                self.log_info(format!("Can't Back Up the Library: {}", err));
+                return self.open_database(self.db_path.clone());
+            }
+            self.log_info(format!("Moved the Old Library to {}", backup.display()));
+        }
+
+        let copied = SongBase::create_folder(&self.db_path)
+            .map_err(|err| err.to_string())
+            .and_then(|_| fs::copy(&legacy_db, &self.db_path).map_err(|err| err.to_string()));
+        if let Err(err) = copied {
+            self.log_info(format!("Can't Import {}: {}", legacy_db.display(), err));
+            if backed_up {
+                if let Err(err) = fs::rename(&backup, &self.db_path) {
+                    self.log_info(format!("Can't Put the Library Back: {}", err));
+                }
+            }
+        } else {
+            let migrated = legacy_db.with_extension("db.migrated");
+            if let Err(err) = fs::rename(&legacy_db, &migrated) {
+                self.log_info(format!(
+                    "Imported, but Can't Rename {}: {}",
+                    legacy_db.display(),
+                    err
+                ));
+            }
+            self.legacy_db = None;
+            self.log_info(format!("Imported {}", legacy_db.display()));
+        }
+        self.open_database(self.db_path.clone());
+    }
+
     pub fn run(&mut self, terminal: &mut Tui) -> io::Result<()> {
         self.command = "Hello".to_string();
         self.info.push("Konnichiwa (◔◡◔)".to_string());
+        if let Some(err) = self.db_error.clone() {
+            self.log_info(format!(
+                "Library Unavailable, Using a Temporary One: {}",
+                err
+            ));
+        }
+        if let Some(err) = self.audio_error.clone() {
+            self.log_info(err);
+        }
+        if self.db_error.is_none() {
+            self.watch_library();
+        }
+        if let Some(legacy_db) = &self.legacy_db {
+            self.log_info(format!(
+                "Found an Old Library @ {}, 'db import' Moves It Here",
+                legacy_db.display()
+            ));
+        }
         while !self.exit {
             terminal.draw(|frame| self.render_frame(frame))?;
             self.handle_events()?;
-            let message = self.receiver.try_recv();
-            if let Ok(message) = message {
+            while let Ok(message) = self.receiver.try_recv() {
                 match message {
                     PlayerAction::ConnectionMessage(msg) => self.log_info(msg),
+                    PlayerAction::ScanProgress(progress) => self.scan_progress = Some(progress),
+                    PlayerAction::ScanFinished(result) => {
+                        self.scan_job = None;
+                        self.scan_progress = None;
+                        match result {
+                            Ok(summary) => self.log_info(summary),
+                            Err(err) => self.log_info(err),
+                        }
+                        self.load_utility();
+                    }
+                    PlayerAction::TrackPlayed(song_id, listened_ms) => {
+                        if let Err(err) = self.song_base.record_play(song_id, listened_ms) {
+                            self.log_info(format!("Can't Record the Play: {}", err));
+                        }
+                        if matches!(
+                            self.utility_state,
+                            UtilityState::History(_) | UtilityState::Stats(_)
+                        ) {
+                            self.load_utility();
+                        }
+                    }
+                    PlayerAction::TrackSkipped(song_id) => {
+                        if let Err(err) = self.song_base.record_skip(song_id) {
+                            self.log_info(format!("Can't Record the Skip: {}", err));
+                        }
+                    }
+                    PlayerAction::DupesFound(result) => match result {
+                        Ok(groups) => {
+                            self.log_info(format!("Found {} Duplicate Groups", groups.len()));
+                            self.dupe_groups = Some(groups);
+                        }
+                        Err(err) => self.log_info(err),
+                    },
                 }
             }
 
-            if self.player.is_sink_empty() && !self.player.is_last() {
+            let finished = self.player.check_finished();
+            if finished && self.player.is_stop_after() {
+                match self.player.fire_stop_after() {
+                    Ok(id) => self.log_info(format!(
+                        "Stopped, {} is up next @ {}",
+                        self.player.current_song_name(),
+                        id + 1
+                    )),
+                    Err(PlayerError::LastSong) => self.log_info("Stopped, End of the Queue"),
+                    Err(err) => self.log_info(err),
+                }
+            } else if self.player.is_sink_empty() && !self.player.is_last() {
                 match self.player.next_track() {
                     Ok(id) => self.log_info(format!(
                         "Playing {} @ {}",
@@ -324,6 +1062,26 @@ impl App {
     }
 
     fn handle_key_event(&mut self, key: KeyEvent) {
+        let viewing = matches!(
+            self.utility_state,
+            UtilityState::Playlist(PlaylistActions::View(_))
+        );
+        if let (true, Some(view)) = (viewing, self.viewed_playlist.as_mut()) {
+            // Space and Enter only act on the list while nothing is typed
+            let typing = !self.command.is_empty();
+            match key.code {
+                KeyCode::Up => return view.move_cursor(-1),
+                KeyCode::Down => return view.move_cursor(1),
+                KeyCode::PageUp => return view.move_cursor(-view.page()),
+                KeyCode::PageDown => return view.move_cursor(view.page()),
+                KeyCode::Home => return view.move_cursor(isize::MIN),
+                KeyCode::End => return view.move_cursor(isize::MAX),
+                KeyCode::Char(' ') if !typing => return view.toggle_selected(),
+                KeyCode::Enter if !typing => return self.queue_viewed_playlist(),
+                _ => {}
+            }
+        }
+
         match key.code {
             KeyCode::Char(ch) => self.command.push(ch),
             KeyCode::Enter => self.handle_command(),
@@ -336,7 +1094,14 @@ impl App {
 
     fn handle_command(&mut self) {
         let command = AppActions::parse_command(&self.command);
+        // Anything but a 'y' right after calls it off
+        let pending = self.pending.take();
         match command {
+            AppActions::Confirm => match pending {
+                Some(Pending::DeletePlaylist(id)) => self.delete_playlist(id),
+                Some(Pending::RepairLibrary) => self.check_library(true),
+                None => self.log_info("Nothing to Confirm"),
+            },
             AppActions::Add(playable) => match playable {
                 Playable::None => self.log_info("Specify Song Name".to_string()),
                 Playable::SongByName(song_names) => {
@@ -346,7 +1111,7 @@ impl App {
                         match song {
                             Err(err) => self.log_info(err),
                             Ok(song) => {
-                                let song_name = song.song_name.clone();
+                                let song_name = song.display_name();
                                 match self.player.add_track(song) {
                                     Ok(index) => self.log_info(format!(
                                         "Added {} to queue @ {}",
@@ -363,12 +1128,62 @@ impl App {
                         let song = self.song_base.find_song_by_id(song_id);
                         match song {
                             Ok(song) => {
-                                self.player.add_track(song).unwrap();
+                                let song_name = song.display_name();
+                                match self.player.add_track(song) {
+                                    Ok(index) => self.log_info(format!(
+                                        "Added {} to queue @ {}",
+                                        song_name, index
+                                    )),
+                                    Err(err) => self.log_info(err),
+                                }
                             }
                             Err(err) => self.log_info(format!("Can't add song: {}", err)),
                         }
                     });
                 }
+                Playable::Album(album_id) => match self.song_base.get_album(album_id) {
+                    Err(err) => self.log_info(err),
+                    Ok(album) => {
+                        let album_name = album.playlist_name.clone();
+                        match self.player.add_playlist(album) {
+                            Ok(index) => {
+                                self.log_info(format!("Added Album {} @ {}", album_name, index))
+                            }
+                            Err(err) => self.log_info(err),
+                        }
+                    }
+                },
+                Playable::Favorites => match self.song_base.get_favorites() {
+                    Err(err) => self.log_info(err),
+                    Ok(favorites) if favorites.songs.is_empty() => {
+                        self.log_info("No Favorites Yet, 'fav' Adds the Playing Song")
+                    }
+                    Ok(favorites) => {
+                        let count = favorites.songs.len();
+                        match self.player.add_playlist(favorites) {
+                            Ok(index) => {
+                                self.log_info(format!("Added {} Favorites @ {}", count, index))
+                            }
+                            Err(err) => self.log_info(err),
+                        }
+                    }
+                },
+                Playable::Label(label) => match self.song_base.get_label(&label) {
+                    Err(err) => self.log_info(err),
+                    Ok(labelled) if labelled.songs.is_empty() => {
+                        self.log_info(format!("No Songs Labelled {}", label))
+                    }
+                    Ok(labelled) => {
+                        let count = labelled.songs.len();
+                        match self.player.add_playlist(labelled) {
+                            Ok(index) => self.log_info(format!(
+                                "Added {} Songs Labelled {} @ {}",
+                                count, label, index
+                            )),
+                            Err(err) => self.log_info(err),
+                        }
+                    }
+                },
                 Playable::Playlist(playlist_id) => {
                     if playlist_id == 0 {
                         self.log_info(
@@ -393,7 +1208,10 @@ impl App {
                     }
                 }
             },
-            AppActions::TogglePlayer => self.log_info(self.player.toggle_player()),
+            AppActions::TogglePlayer => {
+                let message = self.player.toggle_player();
+                self.log_info(message)
+            }
             AppActions::Play => match self.player.play(false) {
                 Ok(_) => self.log_info("Track Resumed"),
                 Err(err) => self.log_info(err),
@@ -405,6 +1223,20 @@ impl App {
                 self.player.pause();
                 self.log_info("Paused.");
             }
+            AppActions::Stop => match self.player.stop() {
+                Ok(_) => self.log_info(format!(
+                    "Stopped, Rewound {}",
+                    self.player.current_song_name()
+                )),
+                Err(err) => self.log_info(err),
+            },
+            AppActions::StopAfter => {
+                if self.player.toggle_stop_after() {
+                    self.log_info("Will Stop After the Current Track")
+                } else {
+                    self.log_info("Stop After Cancelled")
+                }
+            }
             AppActions::NextSong => match self.player.next_track() {
                 Ok(index) => {
                     let next_track_log = self
@@ -418,11 +1250,22 @@ impl App {
             },
             AppActions::PrevSong => todo!(),
             AppActions::Fetch(path) => {
-                let return_value = self.song_base.scan_songs(path);
-                let log_info = return_value
-                    .map(|s| format!("Searching {}", s))
-                    .unwrap_or_else(|err| err.to_string());
-                self.log_info(log_info);
+                if let Some(job) = &self.scan_job {
+                    let message = format!(
+                        "Already Scanning {}, 'scan cancel' Stops It",
+                        job.root.display()
+                    );
+                    self.log_info(message);
+                } else {
+                    match self.song_base.scan_songs(path) {
+                        Ok(job) => {
+                            self.log_info(format!("Scanning {}", job.root.display()));
+                            self.scan_progress = Some(ScanProgress::default());
+                            self.scan_job = Some(job);
+                        }
+                        Err(err) => self.log_info(err),
+                    }
+                }
             }
             AppActions::Jump(index) => match usize::try_from(index) {
                 Ok(index) => match self.player.jump_track(index - 1) {
@@ -452,16 +1295,16 @@ impl App {
                         }
                         PlaylistActions::Create(playlist_name, folder_name) => {
                             self.log_info(format!("{:?} {:?}", playlist_name, folder_name));
-                            if folder_name.is_some() {
-                                if playlist_name.is_some() {
-                                    if let Err(err) = self.song_base.create_playlist_from_path(
-                                        playlist_name.unwrap(),
-                                        folder_name.unwrap(),
-                                    ) {
+                            match (playlist_name, folder_name) {
+                                (Some(playlist_name), Some(folder_name)) => {
+                                    if let Err(err) = self
+                                        .song_base
+                                        .create_playlist_from_path(playlist_name, folder_name)
+                                    {
                                         self.log_info(err);
                                     }
-                                } else {
-                                    let folder_name = folder_name.unwrap();
+                                }
+                                (None, Some(folder_name)) => {
                                     if let Err(err) = self.song_base.create_playlist_from_path(
                                         folder_name
                                             .file_name()
@@ -473,39 +1316,226 @@ impl App {
                                         self.log_info(err);
                                     }
                                 }
-                            } else {
-                                if playlist_name.is_none() {
-                                    self.log_info(
-                                        "Specify the song name to create, try 'help playlist'",
-                                    );
-                                } else {
-                                    match self.song_base.create_playlist(playlist_name.unwrap()) {
-                                        Err(err) => self.log_info(err),
-                                        _ => (),
+                                (None, None) => self.log_info(
+                                    "Specify the song name to create, try 'help playlist'",
+                                ),
+                                (Some(playlist_name), None) => {
+                                    if let Err(err) = self.song_base.create_playlist(playlist_name)
+                                    {
+                                        self.log_info(err)
                                     }
                                 }
                             }
                         }
+                        PlaylistActions::CreateSmart(playlist_name, rule) => {
+                            match self.song_base.create_smart_playlist(playlist_name, rule) {
+                                Ok(id) => self.log_info(format!("Created Smart Playlist {}", id)),
+                                Err(err) => self.log_info(err),
+                            }
+                        }
+                        PlaylistActions::View(Some(id)) => match self.song_base.get_playlist(id) {
+                            Ok(playlist) => {
+                                self.viewed_playlist = Some(PlaylistView::new(playlist));
+                                self.utility_state =
+                                    UtilityState::Playlist(PlaylistActions::View(Some(id)));
+                            }
+                            Err(err) => self.log_info(err),
+                        },
+                        PlaylistActions::View(None) => self.log_info("usage: playlist view <id>"),
+                        PlaylistActions::Remove(id, positions) => {
+                            match self.song_base.remove_playlist_songs(id, &positions) {
+                                Ok(removed) => {
+                                    self.log_info(format!(
+                                        "Removed {} Songs from Playlist {}",
+                                        removed, id
+                                    ));
+                                    self.refresh_viewed_playlist(id);
+                                }
+                                Err(err) => self.log_info(err),
+                            }
+                        }
+                        PlaylistActions::Move(id, from, to) => {
+                            match self.song_base.move_playlist_song(id, from, to) {
+                                Ok(()) => {
+                                    self.log_info(format!("Moved Song {} to {}", from, to));
+                                    self.refresh_viewed_playlist(id);
+                                }
+                                Err(err) => self.log_info(err),
+                            }
+                        }
+                        PlaylistActions::Rename(id, playlist_name) => {
+                            match self.song_base.rename_playlist(id, &playlist_name) {
+                                Ok(()) => {
+                                    self.log_info(format!("Renamed Playlist {}", id));
+                                    self.refresh_viewed_playlist(id);
+                                }
+                                Err(err) => self.log_info(err),
+                            }
+                        }
+                        PlaylistActions::Delete(id) => match self.playlist_name(id) {
+                                Some(playlist_name) => {
+                                    self.log_info(format!(
+                                        "Delete Playlist {} ({})? Type 'y' to Confirm",
+                                        playlist_name, id
+                                    ));
+                                    self.pending = Some(Pending::DeletePlaylist(id));
+                                }
+                                None => self.log_info(SongBaseError::EntryNotFound),
+                            },
+                        PlaylistActions::Duplicate(id, playlist_name) => {
+                            let Some(playlist_name) = playlist_name.or_else(|| {
+                                self.playlist_name(id)
+                                    .map(|playlist_name| format!("{} (copy)", playlist_name))
+                            }) else {
+                                self.log_info(SongBaseError::EntryNotFound);
+                                self.command.clear();
+                                return;
+                            };
+                            match self.song_base.duplicate_playlist(id, &playlist_name) {
+                                Ok(copy_id) => self.log_info(format!(
+                                    "Copied Playlist {} to {} ({})",
+                                    id, playlist_name, copy_id
+                                )),
+                                Err(err) => self.log_info(err),
+                            }
+                        }
+                        PlaylistActions::Import(path) => self.import_playlist(&path),
+                        PlaylistActions::Export(id, path) => self.export_playlist(id, &path),
+                        PlaylistActions::Invalid => self.log_info(
+                            "usage: playlist rm/mv/rename/delete/dup/export <id> .. or playlist import <file>",
+                        ),
                         PlaylistActions::AddAll(id) => {
-                            if id.is_none() {
+                            let Some(id) = id else {
                                 self.log_info(
                                     "Please mention playlist id to add to, try 'help playlist'",
                                 );
                                 self.command.clear();
                                 return;
-                            }
+                            };
                             match self
                                 .song_base
-                                .add_playlist_song(id.unwrap(), self.player.get_queue_ids())
+                                .add_playlist_song(id, self.player.get_queue_ids())
                             {
+                                Ok(added) => self.log_info(format!(
+                                    "Added {} Queued Songs to Playlist {}",
+                                    added, id
+                                )),
+                                Err(err) => self.log_info(err),
+                            }
+                        }
+                        PlaylistActions::Add(id, Some(song_names)) if id != 0 => {
+                            let song_ids = self.find_songs(song_names);
+                            match self.song_base.add_playlist_song(id, song_ids) {
+                                Ok(added) => self
+                                    .log_info(format!("Added {} Songs to Playlist {}", added, id)),
                                 Err(err) => self.log_info(err),
-                                _ => (),
                             }
                         }
-                        _ => {}
+                        PlaylistActions::Add(_, _) => self.log_info(
+                            "usage: playlist add <id> <song, song..> or playlist add <id> *",
+                        ),
                     }
                 }
-                _ => self.utility_state = utility,
+                _ => {
+                    self.utility_state = utility;
+                    self.load_utility();
+                }
+            },
+            AppActions::Database(database_action) => match database_action {
+                DatabaseActions::Status => self.utility_state = UtilityState::Status,
+                DatabaseActions::Retry => self.open_database(self.db_path.clone()),
+                DatabaseActions::Open(db_path) => self.open_database(db_path),
+                DatabaseActions::ImportLegacy => self.import_legacy_db(),
+            },
+            AppActions::Library(library_action) => match library_action {
+                LibraryActions::Check(repair) => {
+                    self.check_library(false);
+                    let problems = self
+                        .library_report
+                        .as_ref()
+                        .map_or(0, |report| report.issues.len());
+                    if repair && problems > 0 {
+                        self.log_info(format!("Repair {} Problems? Type 'y' to Confirm", problems));
+                        self.pending = Some(Pending::RepairLibrary);
+                    }
+                }
+                LibraryActions::Dupes => {
+                    self.song_base.find_dupes();
+                    self.dupe_groups = None;
+                    self.utility_state = UtilityState::Dupes;
+                    self.log_info("Looking for Duplicates");
+                }
+                LibraryActions::Merge(group, keep) => self.merge_dupes(group, keep),
+                LibraryActions::Invalid => self.log_info(
+                    "usage: library check [--fix] / library dupes / library merge <n> [copy]",
+                ),
+            },
+            AppActions::Scan(scan_action) => {
+                let result = match scan_action {
+                    ScanActions::Exclude(pattern) => self
+                        .song_base
+                        .add_exclusion(&pattern)
+                        .map(|_| format!("Scans Will Skip {}", pattern)),
+                    ScanActions::Include(pattern) => self
+                        .song_base
+                        .remove_exclusion(&pattern)
+                        .map(|_| format!("Scans Won't Skip {} Anymore", pattern)),
+                    ScanActions::Depth(depth) => {
+                        self.song_base.set_max_depth(depth).map(|_| match depth {
+                            Some(depth) => format!("Scans Go {} Folders Deep", depth),
+                            None => "Scans Go All the Way Down".to_string(),
+                        })
+                    }
+                    ScanActions::Hidden(scan_hidden) => self
+                        .song_base
+                        .set_skip_hidden(!scan_hidden)
+                        .map(|_| match scan_hidden {
+                            true => "Hidden Folders Will Be Scanned".to_string(),
+                            false => "Hidden Folders Will Be Skipped".to_string(),
+                        }),
+                    ScanActions::Cancel => match &self.scan_job {
+                        Some(job) => {
+                            job.cancel();
+                            Ok(format!("Cancelling Scan of {}", job.root.display()))
+                        }
+                        None => Ok("No Scan Running".to_string()),
+                    },
+                    ScanActions::Invalid => Ok(
+                        "usage: scan exclude/include <glob> / scan depth <n|off> / scan hidden <on|off>"
+                            .to_string(),
+                    ),
+                };
+                match result {
+                    Ok(message) => self.log_info(message),
+                    Err(err) => self.log_info(err),
+                }
+            }
+            AppActions::ExportStats(period, path) => self.export_stats(period, path),
+            AppActions::Rating(rating_action) => self.rate(rating_action),
+            AppActions::Label(label_action) => self.label(label_action),
+            AppActions::Filter(filter_action) => match filter_action {
+                FilterActions::Save(name, query) => {
+                    let query = match (query, &self.utility_state) {
+                        (Some(query), _) => query,
+                        (None, UtilityState::SearchSong(query)) => query.clone(),
+                        (None, _) => {
+                            self.log_info("Search for Something First, or Give the Query");
+                            self.command.clear();
+                            return;
+                        }
+                    };
+                    match self.song_base.save_filter(&name, &query) {
+                        Ok(_) => self.log_info(format!("Saved Filter @{}: {}", name, query)),
+                        Err(err) => self.log_info(err),
+                    }
+                }
+                FilterActions::Remove(name) => match self.song_base.remove_filter(&name) {
+                    Ok(_) => self.log_info(format!("Removed Filter @{}", name)),
+                    Err(err) => self.log_info(err),
+                },
+                FilterActions::Invalid => self.log_info(
+                    "usage: filter [list] / filter save <name> [query] / filter rm <name>",
+                ),
             },
             AppActions::LogMessage(msg) => self.log_info(msg),
             AppActions::Invalid => self.log_info("Can't get that, Check out Top Right ↗️"),
@@ -563,27 +1593,50 @@ impl Widget for &App {
             .split(main_layout[0]);
 
         //Queue Box - Left Full
-        let queue_block = Block::default()
+        let mut queue_block = Block::default()
             .title(" Play Queue ".fg(Color::Red))
             .title_alignment(Alignment::Center)
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
             .border_style(Style::default().fg(Color::White));
 
+        if self.player.is_stop_after() {
+            queue_block = queue_block.title(
+                Title::from(" ⏹ stop after ".fg(Color::Yellow))
+                    .position(Position::Bottom)
+                    .alignment(Alignment::Right),
+            );
+        }
+
         let queue_area = upper_layout[0];
-        let queue_logs: Vec<Line> = self
-            .player
-            .get_queue()
-            .iter()
-            .enumerate()
-            .map(|(index, song_name)| {
-                if index == self.player.current_song() as usize {
-                    Line::from(format!("{}. {}", index + 1, song_name)).fg(Color::Green)
-                } else {
-                    Line::from(format!("{}. {}", index + 1, song_name))
-                }
-            })
-            .collect();
+        let queue_logs: Vec<Line> =
+            self.player
+                .get_queue()
+                .iter()
+                .enumerate()
+                .map(|(index, song)| {
+                    let entry = match song.tags.duration_ms {
+                        Some(duration) => format!(
+                            "{}. {} ({})",
+                            index + 1,
+                            song.display_name(),
+                            format_duration(duration)
+                        ),
+                        None => format!("{}. {}", index + 1, song.display_name()),
+                    };
+                    let rating = format_rating(song.tags.rating, song.favorite);
+                    let line = match rating.is_empty() {
+                        true => Line::from(entry),
+                        false => Line::default()
+                            .spans(vec![entry.into(), format!(" {}", rating).yellow()]),
+                    };
+                    if index == self.player.current_song() as usize {
+                        line.fg(Color::Green)
+                    } else {
+                        line
+                    }
+                })
+                .collect();
 
         let queue_para = Paragraph::new(queue_logs)
             .block(queue_block)
@@ -597,13 +1650,36 @@ impl Widget for &App {
             .split(upper_layout[1]);
 
         //Info Box - right Bottom
-        let info_box = Block::default()
+        let mut info_box = Block::default()
             .title(" Info ".fg(Color::Red))
             .borders(Borders::ALL)
             .border_type(BorderType::Rounded)
             .border_style(Style::default().fg(Color::White))
             .title_alignment(Alignment::Center);
 
+        if let Some(progress) = &self.scan_progress {
+            let folder = progress
+                .current_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            info_box = info_box.title(
+                Title::from(
+                    format!(
+                        " ⟳ {} | {} seen, {} new, {} skipped, {} errors ",
+                        folder,
+                        progress.files_seen,
+                        progress.added,
+                        progress.skipped,
+                        progress.errors
+                    )
+                    .fg(Color::Yellow),
+                )
+                .position(Position::Bottom)
+                .alignment(Alignment::Right),
+            );
+        }
+
         let info_area = right_layout[1];
         let info_lines: Vec<Line> = self
             .info
@@ -623,24 +1699,59 @@ impl Widget for &App {
             .split(right_layout[0]);
 
         let utility_area = top_right_layout[0];
-        match &self.utility_state {
-            UtilityState::Playlist(playlist_command) => {
-                match playlist_command {
-                    PlaylistActions::Show => {
-                        if let Ok(playlist) = self.song_base.get_playlists() {
-                            render_playlist_view(utility_area, buf, &playlist)
-                        }
-                    }
-                    _ => (),
+        match (&self.utility_state, &self.utility_data) {
+            (UtilityState::Playlist(PlaylistActions::Show), _) => {
+                if let Ok(playlist) = self.song_base.get_playlists() {
+                    render_playlist_view(utility_area, buf, &playlist)
                 }
-                // let playlists = vec!["baka".to_string(), "smth".to_string(), "peace".to_string()];
-                // playlist(top_right_layout[0], buf, &playlists);
-                // self.log_info(playlist_command.clone())
             }
-            UtilityState::SearchSong(song_name) => {
-                let song_list = self.song_base.filter_song(song_name);
-                render_search_song(utility_area, buf, song_list.as_ref(), song_name);
+            (UtilityState::Playlist(PlaylistActions::View(_)), _) => {
+                render_playlist(utility_area, buf, self.viewed_playlist.as_ref())
+            }
+            (UtilityState::SearchSong(song_name), Some(UtilityData::Search(song_list))) => {
+                render_search_song(utility_area, buf, song_list.as_ref(), song_name)
+            }
+            (UtilityState::Help, _) => render_utility_home(utility_area, buf),
+            (UtilityState::ScanOptions, _) => render_scan_options(
+                utility_area,
+                buf,
+                self.song_base.get_scan_options().as_ref(),
+            ),
+            (UtilityState::Labels, Some(UtilityData::Labels(labels))) => {
+                render_labels(utility_area, buf, labels.as_ref())
             }
+            (UtilityState::Filters, _) => {
+                render_filters(utility_area, buf, self.song_base.get_filters().as_ref())
+            }
+            (UtilityState::History(_), Some(UtilityData::History(plays))) => {
+                render_history(utility_area, buf, plays.as_ref())
+            }
+            (UtilityState::Stats(_), Some(UtilityData::Stats(stats))) => {
+                render_stats(utility_area, buf, stats.as_ref().as_ref())
+            }
+            (UtilityState::Artists, Some(UtilityData::Artists(artists))) => {
+                render_artists(utility_area, buf, artists.as_ref())
+            }
+            (UtilityState::Albums(artist), Some(UtilityData::Albums(albums))) => {
+                render_albums(utility_area, buf, albums.as_ref(), artist.as_deref())
+            }
+            (UtilityState::Album(_), Some(UtilityData::Album(album))) => {
+                render_album(utility_area, buf, album.as_ref())
+            }
+            (UtilityState::LibraryCheck, _) => {
+                render_library_check(utility_area, buf, self.library_report.as_ref())
+            }
+            (UtilityState::Dupes, _) => {
+                render_dupes(utility_area, buf, self.dupe_groups.as_deref())
+            }
+            (UtilityState::Status, _) => render_status(
+                utility_area,
+                buf,
+                &self.db_path.to_string_lossy(),
+                self.db_error.as_deref(),
+                self.audio_error.as_deref(),
+                &self.song_base.get_roots().unwrap_or_default(),
+            ),
             _ => (),
         }
 
@@ -652,12 +1763,13 @@ impl Widget for &App {
             .title_alignment(Alignment::Center);
 
         let help_area = top_right_layout[1];
-        let help_lines = "Use the Command At the Bottom :)\n\nFetch [dir]: Scan and add songs in the directory
-        Add [song_name]: Append the Song to the queue\nPause/Play/Resume: Self Explanatory\nJump [index]: Skip to the song in the queue
-        Next: Advance to next Song\nPrev: Rollback to previous Song\nQuit/Exit: Close the App\nManual: Open up the Help Page";
+        let help_lines = "Use the Command At the Bottom :)\n\nFetch [dir]: Scan and add songs in the directory\nScan Cancel: Stop the running scan\nScan Settings/Exclude [glob]: What Scans Skip
+        Add [song_name]: Append the Song to the queue
+        Search [query]: artist:x year:>1990 dur:<5m -live @filter label:x played:<30 sort:-plays limit:50\nFilter Save [name] [query]: Name a Search\nPause/Play/Resume: Self Explanatory
+        Stop: Halt and Rewind the Track\nStop-After: Stop once the Track Ends\nJump [index]: Skip to the song in the queue
+        Next: Advance to next Song\nPrev: Rollback to previous Song\nArtists/Albums [artist]/Album [id]: Browse\nHistory [count]: Recently Played\nRate [1-5] [song]/Fav/Unfav [song]: Rate the Playing or Named Song\nAdd -f: Queue All Favorites\nLabel Add/Rm [label] [songs]/Labels: Tag Songs, Add -l [label] Queues Them\nStats [week/month/year/all]: Listening Stats\nExport Stats [period] [file]: Save Them as JSON\nPlaylist Create [name] -r [query]: Smart Playlist, e.g. rating:>=4 -played:<30 sort:random limit:100\nPlaylist View [id]: Arrows Scroll, Space Picks, Enter Queues\nPlaylist Rm/Mv [id] [n] [to]: Edit a Playlist\nPlaylist Rename/Delete/Dup [id] [name]: Manage Playlists\nPlaylist Import [file]/Export [id] [file]: M3U Playlists\nAdd -a [album id]: Queue a Whole Album\nLibrary Check [--fix]: Find Broken Entries\nLibrary Dupes/Merge [n]: Find and Merge Copies\nDb [retry/open/import]: Library Status\nQuit/Exit: Close the App\nManual: Open up the Help Page";
         let help_lines: Vec<Line> = help_lines
             .lines()
-            .into_iter()
             .map(|line| Line::raw(line).fg(Color::Blue))
             .collect();
 
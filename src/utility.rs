@@ -1,18 +1,150 @@
+use std::{cell::Cell, collections::BTreeSet, path::PathBuf};
+
 use ratatui::{
     buffer::Buffer,
-    layout::{Alignment, Rect},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Style, Stylize},
     text::Line,
-    widgets::{Block, BorderType, Borders, Paragraph, Widget, Wrap},
+    widgets::{BarChart, Block, BorderType, Borders, Paragraph, Sparkline, Widget, Wrap},
 };
 
-use crate::{error::SongBaseError, song::PlaylistActions};
+use crate::{
+    error::SongBaseError,
+    song::{AlbumSummary, ArtistSummary, PlayRecord, Playlist, PlaylistActions, SearchHit},
+    song_base::{DupeGroup, IntegrityReport, IssueKind, ListeningStats, ScanOptions, StatsPeriod},
+};
 
 #[derive(PartialEq, Debug)]
 pub enum UtilityState {
     Playlist(PlaylistActions),
     SearchSong(String),
     Help,
+    Status,
+    LibraryCheck,
+    Artists,
+    Albums(Option<String>),
+    Album(u32),
+    Filters,
+    ScanOptions,
+    Dupes,
+    /// How many of the latest plays to list.
+    History(usize),
+    Stats(StatsPeriod),
+    Labels,
+}
+
+/// The rows the utility pane shows, loaded when its command runs and again
+/// when they change, not on every frame.
+pub enum UtilityData {
+    Search(Result<Vec<SearchHit>, SongBaseError>),
+    History(Result<Vec<PlayRecord>, SongBaseError>),
+    Labels(Result<Vec<(String, u32)>, SongBaseError>),
+    Artists(Result<Vec<ArtistSummary>, SongBaseError>),
+    Albums(Result<Vec<AlbumSummary>, SongBaseError>),
+    Album(Result<Playlist, SongBaseError>),
+    Stats(Box<Result<ListeningStats, SongBaseError>>),
+}
+
+/// A playlist open in the utility pane, with a cursor to move through it
+/// and the entries picked for queueing.
+pub struct PlaylistView {
+    pub playlist: Playlist,
+    pub cursor: usize,
+    /// First entry shown.
+    pub offset: usize,
+    /// Ids of the picked songs, so the picks follow them when the playlist
+    /// is reordered.
+    pub selected: BTreeSet<u32>,
+    /// Rows the list got when last drawn, for paging.
+    height: Cell<usize>,
+}
+
+impl PlaylistView {
+    pub fn new(playlist: Playlist) -> Self {
+        Self {
+            playlist,
+            cursor: 0,
+            offset: 0,
+            selected: BTreeSet::new(),
+            height: Cell::new(1),
+        }
+    }
+
+    /// The same place in a reloaded playlist, as far as it still reaches.
+    /// Picks of songs that are no longer in it are dropped.
+    pub fn reload(&mut self, playlist: Playlist) {
+        let len = playlist.songs.len();
+        self.playlist = playlist;
+        self.cursor = self.cursor.min(len.saturating_sub(1));
+        let songs = &self.playlist.songs;
+        self.selected
+            .retain(|&song_id| songs.iter().any(|song| song.song_id == song_id));
+    }
+
+    /// Moves the cursor by `rows`, scrolling to keep it in sight.
+    pub fn move_cursor(&mut self, rows: isize) {
+        let last = self.playlist.songs.len().saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(rows).min(last);
+
+        let height = self.height.get().max(1);
+        if self.cursor < self.offset {
+            self.offset = self.cursor;
+        } else if self.cursor >= self.offset + height {
+            self.offset = self.cursor + 1 - height;
+        }
+    }
+
+    pub fn page(&self) -> isize {
+        self.height.get().max(1) as isize
+    }
+
+    pub fn toggle_selected(&mut self) {
+        let Some(song) = self.playlist.songs.get(self.cursor) else {
+            return;
+        };
+        if !self.selected.remove(&song.song_id) {
+            self.selected.insert(song.song_id);
+        }
+    }
+
+    /// Ids of the picked songs in playlist order, every song when none are.
+    pub fn queued_ids(&self) -> Vec<u32> {
+        self.playlist
+            .songs
+            .iter()
+            .map(|song| song.song_id)
+            .filter(|song_id| self.selected.is_empty() || self.selected.contains(song_id))
+            .collect()
+    }
+}
+
+/// 215000 -> "3:35", an hour or more -> "1:02:03"
+pub fn format_duration(duration_ms: u64) -> String {
+    let seconds = duration_ms / 1000;
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// 4 stars on a favorite -> "★★★★☆ ♥", empty when there's neither.
+pub fn format_rating(rating: Option<u8>, favorite: bool) -> String {
+    let stars = rating.map(|stars| {
+        let stars = stars.min(5) as usize;
+        format!("{}{}", "★".repeat(stars), "☆".repeat(5 - stars))
+    });
+    let heart = favorite.then(|| "♥".to_string());
+    [stars, heart]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 fn render_block<'a>(name: &str) -> Block<'a> {
@@ -27,16 +159,31 @@ fn render_block<'a>(name: &str) -> Block<'a> {
 pub fn render_search_song(
     rect: Rect,
     buf: &mut Buffer,
-    song_list: Result<&Vec<(String, u32)>, &SongBaseError>,
+    song_list: Result<&Vec<SearchHit>, &SongBaseError>,
     song_name: &str,
 ) {
     let name = format!("SearchSong: {}", song_name);
     let block = render_block(&name);
 
+    if let Err(SongBaseError::InvalidQuery(err)) = song_list {
+        // Point at the offending spot under the query itself
+        let lines = vec![
+            Line::raw(""),
+            Line::raw(song_name.to_string()).blue(),
+            Line::raw(format!("{}^", " ".repeat(err.position))).red(),
+            Line::raw(err.message.clone()).red(),
+        ];
+        Paragraph::new(lines)
+            .left_aligned()
+            .block(block)
+            .render(rect, buf);
+        return;
+    }
+
     if song_list.is_err() {
         let line = Line::raw(format!(
             "Can't get the data, but got an error: {:?}",
-            song_list.err().take().unwrap().to_string()
+            song_list.err().unwrap().to_string()
         ));
         Paragraph::new(line)
             .centered()
@@ -48,10 +195,7 @@ pub fn render_search_song(
 
     let song_list = song_list.unwrap();
     if song_list.is_empty() {
-        let line = vec![
-            Line::raw(""),
-            Line::raw(format!("That's Empty!, gambare gambare")),
-        ];
+        let line = vec![Line::raw(""), Line::raw("That's Empty!, gambare gambare")];
         Paragraph::new(line)
             .centered()
             .block(block)
@@ -63,11 +207,25 @@ pub fn render_search_song(
     let mut lines: Vec<Line> = song_list
         .iter()
         .map(|song| {
-            let song_id = format!(" ({})", song.1);
-            Line::default().spans(vec![song.0.as_str().blue(), song_id.red()])
+            let song_id = format!(" ({})", song.song_id);
+            let mut spans = vec![song.display_name.as_str().blue(), song_id.red()];
+            if let Some(score) = song.fuzzy_score {
+                spans.push(format!(" ~{}%", score).yellow());
+            }
+            if let Some(detail) = &song.detail {
+                spans.push(format!(" {}", detail).green());
+            }
+            let rating = format_rating(song.rating, song.favorite);
+            if !rating.is_empty() {
+                spans.push(format!(" {}", rating).yellow());
+            }
+            Line::default().spans(spans)
         })
         .collect();
 
+    if song_list.iter().any(|song| song.fuzzy_score.is_some()) {
+        lines.insert(0, Line::raw("No exact match, closest spellings:").yellow());
+    }
     lines.insert(0, Line::raw(""));
 
     let para = Paragraph::new(lines)
@@ -78,12 +236,306 @@ pub fn render_search_song(
     para.render(rect, buf);
 }
 
-pub fn render_playlist_view(rect: Rect, buf: &mut Buffer, playlist_names: &Vec<(u8, String)>) {
+fn render_error(rect: Rect, buf: &mut Buffer, block: Block, err: &SongBaseError) {
+    Paragraph::new(Line::raw(format!(
+        "Can't get the data, but got an error: {}",
+        err
+    )))
+    .centered()
+    .block(block)
+    .wrap(Wrap { trim: true })
+    .render(rect, buf);
+}
+
+pub fn render_artists(
+    rect: Rect,
+    buf: &mut Buffer,
+    artists: Result<&Vec<ArtistSummary>, &SongBaseError>,
+) {
+    let block = render_block("Artists");
+    let artists = match artists {
+        Ok(artists) => artists,
+        Err(err) => return render_error(rect, buf, block, err),
+    };
+
+    let mut lines: Vec<Line> = artists
+        .iter()
+        .map(|artist| {
+            let detail = format!(
+                " {} albums, {} tracks, {}",
+                artist.albums,
+                artist.tracks,
+                format_duration(artist.duration_ms)
+            );
+            Line::default().spans(vec![artist.artist.as_str().blue(), detail.red()])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::raw("No tagged songs yet, try 'fetch'"));
+    }
+    lines.insert(0, Line::raw(""));
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+pub fn render_history(
+    rect: Rect,
+    buf: &mut Buffer,
+    plays: Result<&Vec<PlayRecord>, &SongBaseError>,
+) {
+    let block = render_block("History");
+    let plays = match plays {
+        Ok(plays) => plays,
+        Err(err) => return render_error(rect, buf, block, err),
+    };
+
+    let mut lines: Vec<Line> = plays
+        .iter()
+        .map(|play| {
+            let detail = format!(" ({}) {}", play.song_id, format_duration(play.listened_ms));
+            Line::default().spans(vec![
+                format!("{} ", play.played_at).green(),
+                play.display_name.as_str().blue(),
+                detail.red(),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::raw("Nothing played yet"));
+    }
+    lines.insert(0, Line::raw(""));
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+pub fn render_albums(
+    rect: Rect,
+    buf: &mut Buffer,
+    albums: Result<&Vec<AlbumSummary>, &SongBaseError>,
+    artist: Option<&str>,
+) {
+    let name = match artist {
+        Some(artist) => format!("Albums: {}", artist),
+        None => "Albums".to_string(),
+    };
+    let block = render_block(&name);
+    let albums = match albums {
+        Ok(albums) => albums,
+        Err(err) => return render_error(rect, buf, block, err),
+    };
+
+    let mut lines: Vec<Line> = albums
+        .iter()
+        .map(|album| {
+            let year = album
+                .year
+                .map(|year| format!(" {}", year))
+                .unwrap_or_default();
+            let detail = format!(
+                "{} - {} tracks, {}",
+                year,
+                album.tracks,
+                format_duration(album.duration_ms)
+            );
+            Line::default().spans(vec![
+                format!("({}) ", album.album_id).red(),
+                album.album_name.as_str().blue(),
+                format!(" by {}", album.album_artist).green(),
+                detail.red(),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::raw("That's Empty!, gambare gambare"));
+    }
+    lines.insert(0, Line::raw(""));
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+pub fn render_album(rect: Rect, buf: &mut Buffer, album: Result<&Playlist, &SongBaseError>) {
+    let album = match album {
+        Ok(album) => album,
+        Err(err) => return render_error(rect, buf, render_block("Album"), err),
+    };
+    let block = render_block(&album.playlist_name);
+
+    let total_ms: u64 = album
+        .songs
+        .iter()
+        .filter_map(|song| song.tags.duration_ms)
+        .sum();
+
+    let mut lines = vec![
+        Line::raw(""),
+        Line::raw(format!(
+            "{} tracks, {}",
+            album.songs.len(),
+            format_duration(total_ms)
+        ))
+        .green(),
+    ];
+    lines.extend(album.songs.iter().map(|song| {
+        let track = song
+            .tags
+            .track_number
+            .map(|track| format!("{:>2}. ", track))
+            .unwrap_or_else(|| " -. ".to_string());
+        let duration = song
+            .tags
+            .duration_ms
+            .map(|duration| format!(" {}", format_duration(duration)))
+            .unwrap_or_default();
+        Line::default().spans(vec![
+            track.red(),
+            song.display_name().blue(),
+            duration.red(),
+        ])
+    }));
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+pub fn render_filters(
+    rect: Rect,
+    buf: &mut Buffer,
+    filters: Result<&Vec<(String, String)>, &SongBaseError>,
+) {
+    let block = render_block("Saved Filters");
+    let filters = match filters {
+        Ok(filters) => filters,
+        Err(err) => return render_error(rect, buf, block, err),
+    };
+
+    let mut lines: Vec<Line> = filters
+        .iter()
+        .map(|(name, query)| {
+            Line::default().spans(vec![format!("@{} ", name).red(), query.as_str().blue()])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::raw("None yet, try 'filter save <name> <query>'"));
+    }
+    lines.insert(0, Line::raw(""));
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+pub fn render_labels(
+    rect: Rect,
+    buf: &mut Buffer,
+    labels: Result<&Vec<(String, u32)>, &SongBaseError>,
+) {
+    let block = render_block("Labels");
+    let labels = match labels {
+        Ok(labels) => labels,
+        Err(err) => return render_error(rect, buf, block, err),
+    };
+
+    let mut lines: Vec<Line> = labels
+        .iter()
+        .map(|(label, songs)| {
+            Line::default().spans(vec![
+                label.as_str().blue(),
+                format!(" {} songs", songs).green(),
+            ])
+        })
+        .collect();
+    if lines.is_empty() {
+        lines.push(Line::raw("None yet, try 'label add <label> <song>'"));
+    } else {
+        lines.push(Line::raw(""));
+        lines.push(
+            Line::raw("'search label:<label>' lists them, 'add -l <label>' queues them").yellow(),
+        );
+    }
+    lines.insert(0, Line::raw(""));
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+pub fn render_scan_options(
+    rect: Rect,
+    buf: &mut Buffer,
+    options: Result<&ScanOptions, &SongBaseError>,
+) {
+    let block = render_block("Scan Settings");
+    let options = match options {
+        Ok(options) => options,
+        Err(err) => return render_error(rect, buf, block, err),
+    };
+
+    let depth = options
+        .max_depth
+        .map_or("Unlimited".to_string(), |depth| depth.to_string());
+    let hidden = if options.skip_hidden {
+        "Skipped"
+    } else {
+        "Scanned"
+    };
+    let mut lines = vec![
+        Line::raw(""),
+        Line::default().spans(vec!["Max Depth: ".blue(), depth.green()]),
+        Line::default().spans(vec!["Hidden Folders: ".blue(), hidden.green()]),
+        Line::raw(""),
+        Line::raw("Excluded:").blue(),
+    ];
+    lines.extend(
+        options
+            .exclusions
+            .iter()
+            .map(|pattern| Line::raw(pattern.as_str()).red()),
+    );
+    lines.push(Line::raw(""));
+    lines.push(Line::raw("A .bzignore file excludes globs in its folder").yellow());
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+pub fn render_playlist_view(
+    rect: Rect,
+    buf: &mut Buffer,
+    playlist_names: &[(u8, String, Option<String>)],
+) {
     let block = render_block("Playlist");
 
     let lines: Vec<Line> = playlist_names
         .iter()
-        .map(|(index, song)| Line::raw(format!("{} ({})", song, index)))
+        .map(|(index, song, rule)| match rule {
+            Some(rule) => Line::default().spans(vec![
+                format!("{} ({}) ", song, index).into(),
+                format!("smart: {}", rule).yellow(),
+            ]),
+            None => Line::raw(format!("{} ({})", song, index)),
+        })
         .collect();
 
     let para = Paragraph::new(lines)
@@ -94,6 +546,353 @@ pub fn render_playlist_view(rect: Rect, buf: &mut Buffer, playlist_names: &Vec<(
     para.render(rect, buf);
 }
 
+/// One playlist's songs with their lengths, the rule a smart playlist was
+/// filled from, and the cursor and picked entries.
+pub fn render_playlist(rect: Rect, buf: &mut Buffer, view: Option<&PlaylistView>) {
+    let Some(view) = view else {
+        return render_block("Playlist").render(rect, buf);
+    };
+    let playlist = &view.playlist;
+    let block = render_block(&playlist.playlist_name);
+    let inner = block.inner(rect);
+    block.render(rect, buf);
+
+    let total_ms: u64 = playlist
+        .songs
+        .iter()
+        .filter_map(|song| song.tags.duration_ms)
+        .sum();
+    let mut header = vec![Line::raw("")];
+    if let Some(rule) = &playlist.rule {
+        header.push(Line::raw(format!("Rule: {}", rule)).yellow());
+    }
+    header.push(
+        Line::raw(format!(
+            "{} tracks, {}, {} picked",
+            playlist.songs.len(),
+            format_duration(total_ms),
+            view.selected.len()
+        ))
+        .green(),
+    );
+    header.push(Line::raw("Arrows scroll, Space picks, Enter queues").yellow());
+
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(header.len() as u16), Constraint::Fill(1)])
+        .split(inner);
+    Paragraph::new(header).render(layout[0], buf);
+
+    let height = (layout[1].height as usize).max(1);
+    view.height.set(height);
+    // The pane may have shrunk since the cursor last moved
+    let offset = view
+        .offset
+        .max((view.cursor + 1).saturating_sub(height))
+        .min(view.cursor);
+
+    let width = playlist.songs.len().to_string().len();
+    let lines: Vec<Line> = playlist
+        .songs
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .take(height)
+        .map(|(index, song)| {
+            let picked = match view.selected.contains(&song.song_id) {
+                true => "+".green(),
+                false => " ".into(),
+            };
+            let duration = song
+                .tags
+                .duration_ms
+                .map(|duration| format!(" {}", format_duration(duration)))
+                .unwrap_or_default();
+            let line = Line::default().spans(vec![
+                format!("{:>width$}. ", index + 1).red(),
+                picked,
+                song.display_name().blue(),
+                duration.red(),
+            ]);
+            match index == view.cursor {
+                true => line.reversed(),
+                false => line,
+            }
+        })
+        .collect();
+    Paragraph::new(lines).render(layout[1], buf);
+}
+
 pub fn render_utility_home(rect: Rect, buf: &mut Buffer) {
     let block = render_block("Utility Zone");
+
+    let lines = vec![
+        Line::raw(""),
+        Line::raw("Search, Playlists and more show up here"),
+    ];
+
+    Paragraph::new(lines)
+        .centered()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+pub fn render_status(
+    rect: Rect,
+    buf: &mut Buffer,
+    db_path: &str,
+    db_error: Option<&str>,
+    audio_error: Option<&str>,
+    roots: &[PathBuf],
+) {
+    let block = render_block("Status");
+
+    let mut lines = vec![Line::raw("")];
+    match db_error {
+        None => lines.push(Line::default().spans(vec!["Library: ".blue(), db_path.green()])),
+        Some(err) => {
+            lines.push(Line::default().spans(vec!["Library: ".blue(), "Temporary".red()]));
+            lines.push(Line::raw(format!("Can't open {}: {}", db_path, err)).yellow());
+            lines.push(Line::raw("Try 'db retry' or 'db open <path>'").blue());
+        }
+    }
+    lines.push(Line::raw(""));
+    match audio_error {
+        None => lines.push(Line::default().spans(vec!["Audio: ".blue(), "Ready".green()])),
+        Some(err) => {
+            lines.push(Line::default().spans(vec!["Audio: ".blue(), "Unavailable".red()]));
+            lines.push(Line::raw(err.to_string()).yellow());
+        }
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::raw("Watched Folders:").blue());
+    if roots.is_empty() {
+        lines.push(Line::raw("None yet, 'fetch <dir>' adds one").yellow());
+    }
+    lines.extend(
+        roots
+            .iter()
+            .map(|root| Line::raw(root.to_string_lossy().to_string()).green()),
+    );
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+pub fn render_library_check(rect: Rect, buf: &mut Buffer, report: Option<&IntegrityReport>) {
+    let block = render_block("Library Check");
+
+    let Some(report) = report else {
+        Paragraph::new(vec![Line::raw(""), Line::raw("Run 'library check' first")])
+            .centered()
+            .block(block)
+            .render(rect, buf);
+        return;
+    };
+
+    let mut lines = vec![Line::raw(""), Line::raw(report.summary()).green()];
+    for kind in [
+        IssueKind::Schema,
+        IssueKind::ForeignKey,
+        IssueKind::DanglingLink,
+        IssueKind::MissingFile,
+        IssueKind::DuplicatePath,
+    ] {
+        let count = report.count(kind);
+        let status = format!("{}: {}", kind, count);
+        lines.push(if count == 0 {
+            Line::raw(status).blue()
+        } else {
+            Line::raw(status).red()
+        });
+    }
+
+    if !report.issues.is_empty() {
+        lines.push(Line::raw(""));
+        lines.extend(
+            report
+                .issues
+                .iter()
+                .map(|issue| Line::raw(format!("{}: {}", issue.kind, issue.detail)).yellow()),
+        );
+        if report.repaired.is_none() {
+            lines.push(Line::raw(""));
+            lines.push(Line::raw("'library check --fix' repairs these, after a 'y'").blue());
+        }
+    }
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+/// `None` while the library is still being looked through.
+pub fn render_dupes(rect: Rect, buf: &mut Buffer, groups: Option<&[DupeGroup]>) {
+    let block = render_block("Duplicates");
+
+    let Some(groups) = groups else {
+        Paragraph::new(vec![Line::raw(""), Line::raw("Looking for duplicates...")])
+            .centered()
+            .block(block)
+            .render(rect, buf);
+        return;
+    };
+    if groups.is_empty() {
+        Paragraph::new(vec![Line::raw(""), Line::raw("No duplicates found")])
+            .centered()
+            .block(block)
+            .render(rect, buf);
+        return;
+    }
+
+    let mut lines = vec![Line::raw("")];
+    for (group_index, group) in groups.iter().enumerate() {
+        lines.push(Line::raw(format!("{}. {}", group_index + 1, group.kind)).blue());
+        for (song_index, song) in group.songs.iter().enumerate() {
+            let bitrate = song
+                .bitrate_kbps()
+                .map_or("?".to_string(), |kbps| kbps.to_string());
+            let entry = format!(
+                "  {}) {} {}kbps {}",
+                song_index + 1,
+                song.format(),
+                bitrate,
+                song.song_path
+            );
+            lines.push(match song_index {
+                0 => Line::raw(format!("{} (keep)", entry)).green(),
+                _ => Line::raw(entry),
+            });
+        }
+    }
+    lines.push(Line::raw(""));
+    lines.push(Line::raw("'library merge <n> [copy]' keeps one, playlists follow it").yellow());
+
+    Paragraph::new(lines)
+        .left_aligned()
+        .block(block)
+        .wrap(Wrap { trim: true })
+        .render(rect, buf);
+}
+
+/// Entries of each top list shown, the export has them all.
+const STATS_TOP_SHOWN: usize = 5;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+pub fn render_stats(rect: Rect, buf: &mut Buffer, stats: Result<&ListeningStats, &SongBaseError>) {
+    let block = render_block("Stats");
+    let stats = match stats {
+        Ok(stats) => stats,
+        Err(err) => return render_error(rect, buf, block, err),
+    };
+
+    let inner = block.inner(rect);
+    block.render(rect, buf);
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(7),
+            Constraint::Length(5),
+        ])
+        .split(inner);
+
+    let mut lines = vec![
+        Line::raw(""),
+        Line::raw(stats.period.to_string()).blue(),
+        Line::raw(format!(
+            "{} Plays, {} Listened, Longest Streak {} Days",
+            stats.plays,
+            format_duration(stats.listened_ms),
+            stats.longest_streak_days
+        ))
+        .green(),
+    ];
+    for (name, entries) in [
+        ("Top Songs", &stats.top_songs),
+        ("Top Artists", &stats.top_artists),
+        ("Top Albums", &stats.top_albums),
+    ] {
+        if entries.is_empty() {
+            continue;
+        }
+        lines.push(Line::raw(""));
+        lines.push(Line::raw(name).blue());
+        for (index, entry) in entries.iter().take(STATS_TOP_SHOWN).enumerate() {
+            lines.push(Line::default().spans(vec![
+                format!("{}. ", index + 1).red(),
+                entry.name.as_str().into(),
+                format!(" {} plays", entry.plays).green(),
+            ]));
+        }
+    }
+    if stats.plays == 0 {
+        lines.push(Line::raw(""));
+        lines.push(Line::raw("Nothing played yet"));
+    }
+    Paragraph::new(lines)
+        .left_aligned()
+        .wrap(Wrap { trim: true })
+        .render(layout[0], buf);
+
+    let weekdays: Vec<(&str, u64)> = WEEKDAYS
+        .iter()
+        .copied()
+        .zip(stats.plays_by_weekday)
+        .collect();
+    BarChart::default()
+        .block(Block::default().title(" Plays by Day ".fg(Color::Red)))
+        .data(weekdays.as_slice())
+        .bar_width(3)
+        .bar_gap(1)
+        .bar_style(Style::default().fg(Color::Blue))
+        .value_style(Style::default().fg(Color::Black).bg(Color::Blue))
+        .render(layout[1], buf);
+
+    Sparkline::default()
+        .block(Block::default().title(" Plays by Hour, 0h to 23h ".fg(Color::Red)))
+        .data(&stats.plays_by_hour)
+        .style(Style::default().fg(Color::Green))
+        .render(layout[2], buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{song::Song, tags::Tags};
+
+    fn playlist(song_ids: &[u32]) -> Playlist {
+        let mut playlist = Playlist::new("Picks");
+        for &song_id in song_ids {
+            playlist.add_song(Song {
+                song_id,
+                song_name: format!("{}.mp3", song_id),
+                song_path: PathBuf::from(format!("{}.mp3", song_id)),
+                tags: Tags::default(),
+                favorite: false,
+            });
+        }
+        playlist
+    }
+
+    #[test]
+    fn picks_follow_their_songs_through_a_reload() {
+        let mut view = PlaylistView::new(playlist(&[1, 2, 3, 4, 5]));
+        view.move_cursor(1);
+        view.toggle_selected();
+        view.move_cursor(2);
+        view.toggle_selected();
+        assert_eq!(view.queued_ids(), [2, 4]);
+
+        // `playlist mv 5 1`, then `playlist rm 5` took song 4 out
+        view.reload(playlist(&[5, 1, 2, 3]));
+        assert_eq!(view.queued_ids(), [2]);
+        assert_eq!(view.cursor, 3);
+    }
 }